@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use srt_translator::*;
+
+const SUBS_COUNT: u32 = 5000;
+
+const LINES: [&str; 8] = [
+  "I don't think we should go there tonight.",
+  "- Where have you been?\n- Nowhere, just walking around.",
+  "<i>The ship's captain refused to answer.</i>",
+  "Whatever happens, stay close to me.",
+  "He's been acting strange ever since the accident.",
+  "We need to find the key before they come back!",
+  "Is that really what you believe?",
+  "Perseverance is the only thing that matters now.",
+];
+
+struct MockTranslator;
+
+impl ETranslate for MockTranslator {
+  // Lines are changed so they do not look like untranslated passthrough
+  fn translate(&self, text: String, _in: Langage, _out: Langage) -> Option<String> {
+    Some(text.lines().map(|line| format!("~{}", line)).collect::<Vec<_>>().join("\r\n"))
+  }
+}
+
+fn generate_subs_text() -> String {
+  (0..SUBS_COUNT).fold(String::new(), |acc, i| {
+    let start = i * 3000;
+    let end = start + 2500;
+
+    acc + &format!("{}\r\n{} --> {}\r\n{}\r\n\r\n", i + 1, format_time(start), format_time(end), LINES[i as usize % LINES.len()])
+  })
+}
+
+fn format_time(ms: u32) -> String {
+  format!("{:02}:{:02}:{:02},{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+fn generate_db_text(lowercase_subs_text: &str) -> String {
+  let word_regex = default_word_regex();
+  let mut words: Vec<&str> = parse_sub_words(lowercase_subs_text, &word_regex).into_keys().collect();
+  words.sort();
+
+  // Leave a single word unknown so only every few subs get highlighted and sent to the translator
+  words.iter().fold(String::new(), |acc, &word| {
+    match word {
+      "perseverance" => acc + "u:" + word + "\r\n",
+      _ => acc + "k:" + word + "\r\n",
+    }
+  })
+}
+
+fn benchmarks(c: &mut Criterion) {
+  let subs_text = generate_subs_text();
+  let lowercase_subs_text = subs_text.to_ascii_lowercase();
+  let db_text = generate_db_text(&lowercase_subs_text);
+  let db_words = parse_db_words(&db_text);
+  let options = TranslateOptions::builder(Langage::EN, Langage::RU)
+    .chunk_delay(Duration::from_secs(0))
+    .build();
+
+  c.bench_function("parse_subs", |b| b.iter(|| parse_subs(&subs_text).unwrap()));
+  let word_regex = default_word_regex();
+  c.bench_function("parse_sub_words", |b| b.iter(|| parse_sub_words(&lowercase_subs_text, &word_regex)));
+  c.bench_function("translate_subs highlight", |b| b.iter(|| {
+    let mut subs = parse_subs(&subs_text).unwrap();
+    translate_subs(&mut subs, &db_words, &MockTranslator, &options).unwrap()
+  }));
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use regex::Regex;
+use translate_core::Langage;
+
+use crate::translate::{parse_color, HighlightMarkup, PreviousCue, TranslateOptions, DEFAULT_MAX_CHUNK_SIZE};
+use crate::words::CaseLocale;
+
+// Options start from the command line defaults, so only the ones differing from them need a setter call.
+// Optional values take either the value or an Option, so command line values are passed as they are parsed.
+pub struct TranslateOptionsBuilder {
+  options: TranslateOptions,
+}
+
+impl TranslateOptions {
+  pub fn builder(source_language: Langage, target_language: Langage) -> TranslateOptionsBuilder {
+    TranslateOptionsBuilder {
+      options: TranslateOptions {
+        source_language,
+        target_language,
+        sentence_mode: false,
+        highlight_color: String::from("#FFFF80"),
+        highlight_markup: HighlightMarkup::Font,
+        translate_all: false,
+        confidence_threshold: None,
+        min_unknown: 1,
+        normalize_text: true,
+        chunk_delay: Duration::from_secs(1),
+        max_requests: None,
+        max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+        skip_failed_chunks: false,
+        merge_short: None,
+        text_filter: None,
+        index_from: None,
+        index_to: None,
+        stopwords: HashSet::new(),
+        skip_acronyms: false,
+        word_notes: false,
+        gloss_format: None,
+        dialogue_dashes: false,
+        translate_urls: false,
+        context: None,
+        translate_karaoke: false,
+        rtl_translation: false,
+        strip_ass_tags: false,
+        detect_skip: false,
+        explain: false,
+        case_locale: CaseLocale::Default,
+        previous_cues: HashMap::new(),
+      },
+    }
+  }
+}
+
+impl TranslateOptionsBuilder {
+  pub fn sentence_mode(mut self, sentence_mode: bool) -> TranslateOptionsBuilder {
+    self.options.sentence_mode = sentence_mode;
+    self
+  }
+
+  // The color is "#RRGGBB" or one of the names the command line takes
+  pub fn highlight_color(mut self, color: &str) -> std::result::Result<TranslateOptionsBuilder, String> {
+    self.options.highlight_color = parse_color(color)?;
+    Ok(self)
+  }
+
+  pub fn highlight_markup(mut self, markup: HighlightMarkup) -> TranslateOptionsBuilder {
+    self.options.highlight_markup = markup;
+    self
+  }
+
+  pub fn translate_all(mut self, translate_all: bool) -> TranslateOptionsBuilder {
+    self.options.translate_all = translate_all;
+    self
+  }
+
+  pub fn confidence_threshold<O>(mut self, confidence_threshold: O) -> TranslateOptionsBuilder where O: Into<Option<f32>> {
+    self.options.confidence_threshold = confidence_threshold.into();
+    self
+  }
+
+  pub fn min_unknown(mut self, min_unknown: usize) -> TranslateOptionsBuilder {
+    self.options.min_unknown = min_unknown;
+    self
+  }
+
+  pub fn normalize_text(mut self, normalize_text: bool) -> TranslateOptionsBuilder {
+    self.options.normalize_text = normalize_text;
+    self
+  }
+
+  pub fn chunk_delay(mut self, chunk_delay: Duration) -> TranslateOptionsBuilder {
+    self.options.chunk_delay = chunk_delay;
+    self
+  }
+
+  pub fn max_chunk_size(mut self, max_chunk_size: usize) -> TranslateOptionsBuilder {
+    self.options.max_chunk_size = max_chunk_size;
+    self
+  }
+
+  pub fn max_requests<O>(mut self, max_requests: O) -> TranslateOptionsBuilder where O: Into<Option<usize>> {
+    self.options.max_requests = max_requests.into();
+    self
+  }
+
+  pub fn skip_failed_chunks(mut self, skip_failed_chunks: bool) -> TranslateOptionsBuilder {
+    self.options.skip_failed_chunks = skip_failed_chunks;
+    self
+  }
+
+  pub fn merge_short<O>(mut self, min_chars: O) -> TranslateOptionsBuilder where O: Into<Option<usize>> {
+    self.options.merge_short = min_chars.into();
+    self
+  }
+
+  pub fn text_filter<O>(mut self, text_filter: O) -> TranslateOptionsBuilder where O: Into<Option<Regex>> {
+    self.options.text_filter = text_filter.into();
+    self
+  }
+
+  pub fn index_from<O>(mut self, index_from: O) -> TranslateOptionsBuilder where O: Into<Option<u32>> {
+    self.options.index_from = index_from.into();
+    self
+  }
+
+  pub fn index_to<O>(mut self, index_to: O) -> TranslateOptionsBuilder where O: Into<Option<u32>> {
+    self.options.index_to = index_to.into();
+    self
+  }
+
+  pub fn stopwords(mut self, stopwords: HashSet<String>) -> TranslateOptionsBuilder {
+    self.options.stopwords = stopwords;
+    self
+  }
+
+  pub fn skip_acronyms(mut self, skip_acronyms: bool) -> TranslateOptionsBuilder {
+    self.options.skip_acronyms = skip_acronyms;
+    self
+  }
+
+  pub fn word_notes(mut self, word_notes: bool) -> TranslateOptionsBuilder {
+    self.options.word_notes = word_notes;
+    self
+  }
+
+  // With a format like RUBY_GLOSS_FORMAT the subs get word glosses instead of translations
+  pub fn gloss_format<'a, O>(mut self, gloss_format: O) -> TranslateOptionsBuilder where O: Into<Option<&'a str>> {
+    self.options.gloss_format = gloss_format.into().map(String::from);
+    self
+  }
+
+  pub fn dialogue_dashes(mut self, dialogue_dashes: bool) -> TranslateOptionsBuilder {
+    self.options.dialogue_dashes = dialogue_dashes;
+    self
+  }
+
+  pub fn translate_urls(mut self, translate_urls: bool) -> TranslateOptionsBuilder {
+    self.options.translate_urls = translate_urls;
+    self
+  }
+
+  pub fn context<'a, O>(mut self, context: O) -> TranslateOptionsBuilder where O: Into<Option<&'a str>> {
+    self.options.context = context.into().map(String::from);
+    self
+  }
+
+  pub fn translate_karaoke(mut self, translate_karaoke: bool) -> TranslateOptionsBuilder {
+    self.options.translate_karaoke = translate_karaoke;
+    self
+  }
+
+  pub fn rtl_translation(mut self, rtl_translation: bool) -> TranslateOptionsBuilder {
+    self.options.rtl_translation = rtl_translation;
+    self
+  }
+
+  pub fn strip_ass_tags(mut self, strip_ass_tags: bool) -> TranslateOptionsBuilder {
+    self.options.strip_ass_tags = strip_ass_tags;
+    self
+  }
+
+  pub fn detect_skip(mut self, detect_skip: bool) -> TranslateOptionsBuilder {
+    self.options.detect_skip = detect_skip;
+    self
+  }
+
+  pub fn explain(mut self, explain: bool) -> TranslateOptionsBuilder {
+    self.options.explain = explain;
+    self
+  }
+
+  pub fn case_locale(mut self, case_locale: CaseLocale) -> TranslateOptionsBuilder {
+    self.options.case_locale = case_locale;
+    self
+  }
+
+  pub fn previous_cues(mut self, previous_cues: HashMap<(u32, NaiveTime, NaiveTime), PreviousCue>) -> TranslateOptionsBuilder {
+    self.options.previous_cues = previous_cues;
+    self
+  }
+
+  pub fn build(self) -> TranslateOptions {
+    self.options
+  }
+}
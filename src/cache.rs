@@ -0,0 +1,182 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use translate_core::*;
+
+// Translations of single chunk lines keyed by the language pair, the context line and the source line
+pub struct TranslationCache {
+  entries: RefCell<HashMap<(String, String, String, String), String>>,
+  refresh: bool,
+  hits: Cell<usize>,
+}
+
+impl TranslationCache {
+  // One "source\ttarget\tline\ttranslation" entry per line, or "source\ttarget\tcontext\tline\ttranslation"
+  // for lines translated with a context. Lines with a tab in them are never cached.
+  pub fn parse(text: &str, refresh: bool) -> TranslationCache {
+    let entries = text.lines()
+      .filter_map(|line| {
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        match fields[..] {
+          [source, target, text, translation] => Some(((String::from(source), String::from(target), String::new(), String::from(text)), String::from(translation))),
+          [source, target, context, text, translation] if !context.is_empty() =>
+            Some(((String::from(source), String::from(target), String::from(context), String::from(text)), String::from(translation))),
+          _ => None,
+        }
+      })
+      .collect();
+
+    TranslationCache {
+      entries: RefCell::new(entries),
+      refresh,
+      hits: Cell::new(0),
+    }
+  }
+
+  pub fn hits(&self) -> usize {
+    self.hits.get()
+  }
+
+  pub fn stringify(&self) -> String {
+    let entries = self.entries.borrow();
+    let mut keys: Vec<&(String, String, String, String)> = entries.keys().collect();
+    keys.sort();
+
+    keys.iter().fold(String::new(), |s, &key| {
+      let context = if key.2.is_empty() { String::new() } else { key.2.clone() + "\t" };
+      s + &key.0 + "\t" + &key.1 + "\t" + &context + &key.3 + "\t" + &entries[key] + "\r\n"
+    })
+  }
+
+  // With refresh set the existing entries are not used, but new translations still replace them
+  fn get(&self, source: Langage, target: Langage, context: &str, line: &str) -> Option<String> {
+    if self.refresh {
+      return None;
+    }
+
+    let translation = self.entries.borrow().get(&(source.to_string(), target.to_string(), String::from(context), String::from(line))).cloned();
+
+    if translation.is_some() {
+      self.hits.set(self.hits.get() + 1);
+    }
+
+    translation
+  }
+
+  fn insert(&self, source: Langage, target: Langage, context: &str, line: &str, translation: &str) {
+    if !context.contains('\t') && !line.contains('\t') && !translation.contains('\t') {
+      self.entries.borrow_mut().insert((source.to_string(), target.to_string(), String::from(context), String::from(line)), String::from(translation));
+    }
+  }
+}
+
+// Only the lines missing from the cache are sent to the translator.
+// The context line opening each chunk is sent with them but kept out of the cache.
+pub struct CachingTranslator<'a, T> {
+  translator: &'a T,
+  cache: &'a TranslationCache,
+  context: Option<&'a str>,
+}
+
+impl<'a, T> CachingTranslator<'a, T> where T: ETranslate {
+  pub fn new(translator: &'a T, cache: &'a TranslationCache, context: Option<&'a str>) -> CachingTranslator<'a, T> {
+    CachingTranslator {
+      translator,
+      cache,
+      context,
+    }
+  }
+}
+
+impl<'a, T> ETranslate for CachingTranslator<'a, T> where T: ETranslate {
+  fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+    let context = self.context.filter(|context| text.starts_with(&format!("{}\r\n", context)));
+    let text = context.map_or(text.as_str(), |context| &text[context.len() + 2..]);
+    let context_key = context.unwrap_or("");
+    let lines: Vec<&str> = text.lines().collect();
+    let mut translations: Vec<Option<String>> = lines.iter().map(|line| self.cache.get(source, target, context_key, line)).collect();
+    let missing_lines: Vec<&str> = lines.iter().zip(translations.iter())
+      .filter(|(_, translation)| translation.is_none())
+      .map(|(&line, _)| line)
+      .collect();
+
+    // The caller drops the first line as the translated context, the context itself stands in for it
+    let with_context = |translated_text: String| match context {
+      Some(context) => format!("{}\r\n{}", context, translated_text),
+      None => translated_text,
+    };
+
+    if missing_lines.is_empty() {
+      return Some(with_context(translations.into_iter().flatten().collect::<Vec<_>>().join("\r\n")));
+    }
+
+    let request_text = with_context(missing_lines.join("\r\n") + "\r\n");
+    let full_translated_text = self.translator.translate(request_text, source, target)?.replace("\\r\\n", "\r\n");
+    let translated_text = match context {
+      Some(_) => full_translated_text.split_once('\n').map_or("", |(_, rest)| rest),
+      None => full_translated_text.as_str(),
+    };
+    let translated_lines: Vec<&str> = translated_text.lines().collect();
+
+    // A mismatch is left for the caller to report when nothing came from the cache
+    if translated_lines.len() != missing_lines.len() {
+      return if missing_lines.len() == lines.len() { Some(full_translated_text) } else { None };
+    }
+
+    let mut translated_lines = translated_lines.into_iter();
+
+    for (line, translation) in lines.iter().zip(translations.iter_mut()) {
+      if translation.is_none() {
+        let translated_line = translated_lines.next().unwrap();
+        self.cache.insert(source, target, context_key, line, translated_line);
+        *translation = Some(String::from(translated_line));
+      }
+    }
+
+    Some(with_context(translations.into_iter().flatten().collect::<Vec<_>>().join("\r\n")))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Records the requests and prefixes each line with "~"
+  struct RecordingTranslator(RefCell<Vec<String>>);
+
+  impl ETranslate for RecordingTranslator {
+    fn translate(&self, text: String, _source: Langage, _target: Langage) -> Option<String> {
+      let translation = text.lines().map(|line| format!("~{}", line)).collect::<Vec<_>>().join("\r\n");
+      self.0.borrow_mut().push(text);
+      Some(translation)
+    }
+  }
+
+  #[test]
+  fn context_is_sent_with_missing_lines_and_kept_out_of_the_cache() {
+    let translator = RecordingTranslator(RefCell::new(Vec::new()));
+    let cache = TranslationCache::parse("", false);
+    let pirate_translator = CachingTranslator::new(&translator, &cache, Some("A pirate movie"));
+
+    let first = pirate_translator.translate(String::from("A pirate movie\r\nThe ship\r\n"), Langage::EN, Langage::RU);
+    let second = pirate_translator.translate(String::from("A pirate movie\r\nThe ship\r\nWe sail\r\n"), Langage::EN, Langage::RU);
+
+    assert_eq!(first.as_deref(), Some("A pirate movie\r\n~The ship"));
+    assert_eq!(second.as_deref(), Some("A pirate movie\r\n~The ship\r\n~We sail"));
+    assert_eq!(*translator.0.borrow(), vec!["A pirate movie\r\nThe ship\r\n", "A pirate movie\r\nWe sail\r\n"]);
+
+    // Translations made under one context are not reused under another one or without any
+    let cooking_translator = CachingTranslator::new(&translator, &cache, Some("A cooking show"));
+    cooking_translator.translate(String::from("A cooking show\r\nThe ship\r\n"), Langage::EN, Langage::RU);
+    CachingTranslator::new(&translator, &cache, None).translate(String::from("The ship\r\n"), Langage::EN, Langage::RU);
+
+    assert_eq!(translator.0.borrow()[2..], ["A cooking show\r\nThe ship\r\n", "The ship\r\n"]);
+    assert_eq!(cache.stringify(), "\
+EN\tRU\tThe ship\t~The ship\r\n\
+EN\tRU\tA cooking show\tThe ship\t~The ship\r\n\
+EN\tRU\tA pirate movie\tThe ship\t~The ship\r\n\
+EN\tRU\tA pirate movie\tWe sail\t~We sail\r\n");
+    assert_eq!(TranslationCache::parse(&cache.stringify(), false).stringify(), cache.stringify());
+  }
+}
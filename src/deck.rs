@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use crate::words::{Word, WordKind, DEFAULT_DB_ORDER};
+
+// With tags given the part of speech of each word goes to a third column, with examples a sub text using the word follows
+pub fn build_deck(words: &[&Word], delimiter: char, tags: Option<&HashMap<&str, &str>>, examples: Option<&HashMap<String, String>>) -> String {
+  words.iter().fold(String::new(), |acc, word| {
+    let tag_field = match tags {
+      Some(tags) => delimiter.to_string() + tags.get(word.text).unwrap_or(&""),
+      None => String::new(),
+    };
+
+    let example_field = match examples {
+      Some(examples) => delimiter.to_string() + &escape_field(examples.get(word.text).map_or("", String::as_str), delimiter),
+      None => String::new(),
+    };
+
+    acc + &escape_field(word.text, delimiter) + &delimiter.to_string() + word.kind.name() + &tag_field + &example_field + "\r\n"
+  })
+}
+
+// Fields holding the delimiter, quotes or line breaks are quoted with inner quotes doubled, as import tools expect
+fn escape_field(field: &str, delimiter: char) -> String {
+  if field.contains(delimiter) || field.contains('"') || field.contains('\n') || field.contains('\r') {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_owned()
+  }
+}
+
+// A section per kind in kind_order, kinds missing from it go last, empty kinds have no section
+pub fn build_markdown(words: &[&Word], kind_order: &[WordKind]) -> String {
+  let mut kinds = kind_order.to_vec();
+  kinds.extend(DEFAULT_DB_ORDER.iter().filter(|kind| !kind_order.contains(kind)));
+
+  kinds.iter().fold(String::from("# Words\n"), |acc, kind| {
+    let items = words.iter()
+      .filter(|word| word.kind == *kind)
+      .fold(String::new(), |items, word| {
+        match word.weight {
+          Some(weight) => items + "- " + word.text + " (" + &weight.to_string() + ")\n",
+          None => items + "- " + word.text + "\n",
+        }
+      });
+
+    if items.is_empty() {
+      return acc;
+    }
+
+    let mut title = String::from(kind.name());
+    title[..1].make_ascii_uppercase();
+
+    acc + "\n## " + &title + "\n\n" + &items
+  })
+}
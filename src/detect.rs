@@ -0,0 +1,40 @@
+use translate_core::Langage;
+
+// The most common short words of each language, they show up in almost any sentence
+const COMMON_WORDS: [(Langage, [&str; 12]); 6] = [
+  (Langage::EN, ["the", "and", "you", "is", "to", "of", "it", "what", "that", "this", "are", "have"]),
+  (Langage::FR, ["le", "la", "les", "et", "est", "je", "tu", "vous", "pas", "une", "que", "c'est"]),
+  (Langage::DE, ["der", "die", "das", "und", "ist", "ich", "du", "nicht", "ein", "eine", "sie", "wir"]),
+  (Langage::NL, ["de", "het", "een", "en", "is", "ik", "je", "niet", "dat", "wat", "van", "zijn"]),
+  (Langage::ES, ["el", "la", "los", "y", "es", "yo", "que", "no", "un", "una", "por", "qué"]),
+  (Langage::IT, ["il", "la", "che", "e", "è", "io", "non", "un", "una", "per", "sono", "cosa"]),
+];
+
+// Fewer common words than this are too little to tell one language from another
+const MIN_COMMON_WORDS: usize = 2;
+
+// Cyrillic text is Russian, for the rest the language with the most common words wins if no other one ties with it
+pub fn detect_language(text: &str) -> Option<Langage> {
+  let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+  let cyrillic_letters = text.chars().filter(|c| ('\u{0400}'..='\u{04FF}').contains(c)).count();
+
+  if letters > 0 && cyrillic_letters * 2 > letters {
+    return Some(Langage::RU);
+  }
+
+  let lowercase_text = text.to_lowercase();
+  let words: Vec<&str> = lowercase_text
+    .split(|c: char| !c.is_alphabetic() && c != '\'')
+    .filter(|word| !word.is_empty())
+    .collect();
+
+  let mut scores: Vec<(Langage, usize)> = COMMON_WORDS.iter()
+    .map(|(language, common_words)| (*language, words.iter().filter(|word| common_words.contains(word)).count()))
+    .collect();
+  scores.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+
+  match scores[..] {
+    [(language, best), (_, second), ..] if best >= MIN_COMMON_WORDS && best > second => Some(language),
+    _ => None,
+  }
+}
@@ -0,0 +1,33 @@
+use translate_core::*;
+
+use crate::libre::LibreTranslate;
+
+// Backends the subs can be translated with
+#[derive(Clone)]
+pub enum Engine {
+  Google,
+  LibreTranslate(LibreTranslate),
+}
+
+impl Engine {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Engine::Google => "google",
+      Engine::LibreTranslate(_) => "libretranslate",
+    }
+  }
+
+  // LibreTranslate models translate each sentence on its own, a context line does not change the others
+  pub fn supports_context(&self) -> bool {
+    matches!(self, Engine::Google)
+  }
+}
+
+impl ETranslate for Engine {
+  fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+    match self {
+      Engine::Google => Google {}.translate(text, source, target),
+      Engine::LibreTranslate(translator) => translator.translate(text, source, target),
+    }
+  }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+#[derive(Debug)]
+pub enum Error {
+  Io(io::Error),
+  Parse {
+    block: usize,
+    reason: String,
+  },
+  Translate {
+    chunk: usize,
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+  },
+  Encoding(FromUtf8Error),
+  Download {
+    url: String,
+    reason: String,
+  },
+  TooLarge {
+    size: u64,
+    limit: u64,
+  },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Io(err) => write!(f, "{}", err),
+      Error::Parse { block, reason } => write!(f, "Failed to parse block {}: {}", block, reason),
+      Error::Translate { chunk, source: Some(source) } => write!(f, "Failed to translate chunk {}: {}", chunk, source),
+      Error::Translate { chunk, source: None } => write!(f, "Failed to translate chunk {}", chunk),
+      Error::Encoding(err) => write!(f, "Text is not valid UTF-8: {}", err),
+      Error::Download { reason, .. } => write!(f, "Download failed: {}", reason),
+      Error::TooLarge { limit, .. } => write!(f, "Input is larger than {} bytes, raise the limit with --max-file-size if the path is right", limit),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io(err) => Some(err),
+      Error::Parse { .. } => None,
+      Error::Translate { source, .. } => source.as_ref().map(|source| source.as_ref() as &(dyn std::error::Error + 'static)),
+      Error::Encoding(err) => Some(err),
+      Error::Download { .. } => None,
+      Error::TooLarge { .. } => None,
+    }
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(err: io::Error) -> Error {
+    Error::Io(err)
+  }
+}
+
+impl From<FromUtf8Error> for Error {
+  fn from(err: FromUtf8Error) -> Error {
+    Error::Encoding(err)
+  }
+}
@@ -0,0 +1,165 @@
+use std::str::FromStr;
+
+use regex::Regex;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SubsFormat {
+  Srt,
+  Vtt,
+  Ass,
+}
+
+impl SubsFormat {
+  pub fn name(&self) -> &'static str {
+    match self {
+      SubsFormat::Srt => "SRT",
+      SubsFormat::Vtt => "WebVTT",
+      SubsFormat::Ass => "ASS",
+    }
+  }
+}
+
+impl FromStr for SubsFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<SubsFormat, Self::Err> {
+    match s {
+      "srt" => Ok(SubsFormat::Srt),
+      "vtt" => Ok(SubsFormat::Vtt),
+      "ass" => Ok(SubsFormat::Ass),
+      _ => Err(format!("Unknown subs format '{}'", s))
+    }
+  }
+}
+
+// The format is told by the content, file extensions are often wrong
+pub fn detect_format(text: &str) -> Option<SubsFormat> {
+  let text = text.trim_start_matches('\u{feff}').trim_start();
+
+  if text.starts_with("WEBVTT") {
+    return Some(SubsFormat::Vtt);
+  }
+
+  if text.contains("[Script Info]") || text.contains("[Events]") {
+    return Some(SubsFormat::Ass);
+  }
+
+  let re_srt_cue = Regex::new(r"(?m)^\d+[ \t]*\r?\n\d{2}:\d{2}:\d{2},\d{3}\s+-->").unwrap();
+
+  if re_srt_cue.is_match(text) {
+    return Some(SubsFormat::Srt);
+  }
+
+  None
+}
+
+// Other formats are turned into SRT text so the SRT parser reads all of them
+pub fn convert_to_srt(text: &str, format: SubsFormat) -> String {
+  match format {
+    SubsFormat::Srt => String::from(text),
+    SubsFormat::Vtt => convert_vtt(text),
+    SubsFormat::Ass => convert_ass(text),
+  }
+}
+
+fn srt_cue(index: usize, start_time: &str, end_time: &str, settings: &str, text: &str) -> String {
+  format!("{}\r\n{} --> {}{}\r\n{}\r\n\r\n", index, start_time, end_time, settings, text)
+}
+
+// VTT times are "mm:ss.ttt" or "hh:mm:ss.ttt"
+fn vtt_time(time: &str) -> Option<String> {
+  let re_time = Regex::new(r"^(?:(\d+):)?(\d{2}):(\d{2})\.(\d{3})$").unwrap();
+  let caps = re_time.captures(time)?;
+  let hours: u32 = caps.get(1).map_or(0, |hours| hours.as_str().parse().unwrap());
+
+  Some(format!("{:02}:{}:{},{}", hours, &caps[2], &caps[3], &caps[4]))
+}
+
+// The header, NOTE, STYLE and REGION blocks are dropped, cue identifiers are replaced by indexes
+fn convert_vtt(text: &str) -> String {
+  let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+  let mut srt = String::new();
+  let mut index = 0;
+
+  for block in text.split("\n\n") {
+    let lines: Vec<&str> = block.lines().collect();
+    let timing_line = match lines.iter().position(|line| line.contains("-->")) {
+      Some(timing_line) => timing_line,
+      None => continue,
+    };
+
+    let mut parts = lines[timing_line].split_whitespace();
+    let start_time = parts.next().and_then(vtt_time);
+    let arrow = parts.next();
+    let end_time = parts.next().and_then(vtt_time);
+
+    if let (Some(start_time), Some("-->"), Some(end_time)) = (start_time, arrow, end_time) {
+      let settings: String = parts.map(|setting| format!(" {}", setting)).collect();
+      index += 1;
+      srt.push_str(&srt_cue(index, &start_time, &end_time, &settings, &lines[timing_line + 1..].join("\r\n")));
+    }
+  }
+
+  srt
+}
+
+// ASS times are "h:mm:ss.cc"
+fn ass_time(time: &str) -> Option<String> {
+  let re_time = Regex::new(r"^(\d+):(\d{2}):(\d{2})\.(\d{2})$").unwrap();
+  let caps = re_time.captures(time.trim())?;
+  let hours: u32 = caps[1].parse().unwrap();
+
+  Some(format!("{:02}:{}:{},{}0", hours, &caps[2], &caps[3], &caps[4]))
+}
+
+// Dialogue lines of the [Events] section are the cues, their fields follow its Format line.
+// Override tags like {\i1} are dropped, "\N" is a line break.
+fn convert_ass(text: &str) -> String {
+  let re_override = Regex::new(r"\{[^}]*\}").unwrap();
+  let mut fields = vec!["Layer", "Start", "End", "Style", "Name", "MarginL", "MarginR", "MarginV", "Effect", "Text"];
+  let mut in_events = false;
+  let mut srt = String::new();
+  let mut index = 0;
+
+  for line in text.lines().map(str::trim) {
+    if line.starts_with('[') {
+      in_events = line.eq_ignore_ascii_case("[Events]");
+      continue;
+    }
+
+    if !in_events {
+      continue;
+    }
+
+    if let Some(format) = line.strip_prefix("Format:") {
+      fields = format.split(',').map(str::trim).collect();
+    } else if let Some(dialogue) = line.strip_prefix("Dialogue:") {
+      let values: Vec<&str> = dialogue.trim_start().splitn(fields.len(), ',').collect();
+      let field = |name: &str| fields.iter().position(|field| field.eq_ignore_ascii_case(name)).and_then(|position| values.get(position).cloned());
+
+      if let (Some(start_time), Some(end_time), Some(text)) = (field("Start").and_then(ass_time), field("End").and_then(ass_time), field("Text")) {
+        let text = re_override.replace_all(text, "").replace("\\N", "\r\n").replace("\\n", "\r\n").replace("\\h", " ");
+        index += 1;
+        srt.push_str(&srt_cue(index, &start_time, &end_time, "", &text));
+      }
+    }
+  }
+
+  srt
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::subs::{parse_subs, DEFAULT_TIME_PRECISION};
+
+  #[test]
+  fn vtt_cue_settings_survive_to_the_output() {
+    let vtt = "WEBVTT\n\nintro\n00:01.000 --> 00:02.500 align:start position:50%\nHello there\n";
+    let subs = parse_subs(&convert_to_srt(vtt, SubsFormat::Vtt)).unwrap();
+
+    assert_eq!(subs[0].settings, " align:start position:50%");
+    assert_eq!(subs[0].text, "Hello there");
+    assert_eq!(subs[0].stringify(DEFAULT_TIME_PRECISION), "1\n00:00:01,000 --> 00:00:02,500 align:start position:50%\nHello there\n\n");
+  }
+}
@@ -0,0 +1,150 @@
+use std::io::Read;
+use std::path::Path;
+
+use encoding_rs::Encoding;
+
+mod builder;
+mod cache;
+mod deck;
+mod detect;
+mod engine;
+mod error;
+mod format;
+mod libre;
+mod log;
+mod patch;
+mod pos;
+mod preview;
+mod run;
+mod subs;
+mod timeout;
+mod trace;
+mod translate;
+mod words;
+
+pub use crate::builder::TranslateOptionsBuilder;
+pub use crate::cache::{CachingTranslator, TranslationCache};
+pub use crate::deck::{build_deck, build_markdown};
+pub use crate::detect::detect_language;
+pub use crate::engine::Engine;
+pub use crate::error::{Error, Result};
+pub use crate::format::{SubsFormat, convert_to_srt, detect_format};
+pub use crate::libre::LibreTranslate;
+pub use crate::log::{json_logs, log_event, quiet, set_json_logs, set_quiet};
+pub use crate::patch::build_patch;
+pub use crate::pos::tag_words;
+pub use crate::preview::build_preview_html;
+pub use crate::run::{Layout, RunOptions, run, run_with_translator};
+pub use crate::subs::{Sub, DEFAULT_MAX_CPS, DEFAULT_TIME_PRECISION, METADATA_MARKER, ORDER_ORIGINAL_FIRST, ORDER_TRANSLATION_FIRST, parse_subs, check_subs, parse_subs_lenient, decode_entities, decode_subs_entities, format_time, reorder_translations, sort_subs_by_time, enforce_min_gap, renumber_subs, split_long_subs, split_subs_on, find_fast_subs, repair_subs_text, dialogue_field, original_line_counts};
+pub use crate::timeout::TimeoutTranslator;
+pub use crate::trace::{CueTrace, ParallelLayout, stringify_parallel_text, stringify_traces};
+pub use crate::translate::{Chunk, HighlightMarkup, PreviousCue, TranslateOptions, TranslationStats, DEFAULT_MAX_CHUNK_SIZE, RUBY_GLOSS_FORMAT, SUPPORTED_LANGUAGES, build_chunks, parse_color, parse_gloss_format, parse_language, parse_previous_cues, translate_subs, translate_subs_with_progress, is_translated_output};
+pub use crate::words::{CaseLocale, Word, WordKind, DEFAULT_DB_ORDER, ENGLISH_STOPWORDS, parse_db_kinds, parse_db_order, is_acronym, strip_acronyms, is_non_linguistic, non_linguistic_spans, strip_non_linguistic, strip_ass_overrides, parse_db_words, excess_new_words, default_word_regex, fold_case, language_word_regex, language_letters_regex, parse_sub_words, find_word_examples, has_any_word, count_sub_words, parse_stopwords, stringify_db_words};
+pub use translate_core::{ETranslate, Langage};
+
+pub fn is_url(file_name: &str) -> bool {
+  file_name.starts_with("http://") || file_name.starts_with("https://")
+}
+
+#[cfg(feature = "url")]
+fn download(url: &str) -> Result<Vec<u8>> {
+  let download_error = |reason: String| Error::Download {
+    url: String::from(url),
+    reason,
+  };
+
+  let client = reqwest::Client::builder()
+    .timeout(std::time::Duration::from_secs(30))
+    .build()
+    .map_err(|err| download_error(err.to_string()))?;
+  let mut response = client.get(url).send().map_err(|err| download_error(err.to_string()))?;
+
+  if !response.status().is_success() {
+    return Err(download_error(response.status().to_string()));
+  }
+
+  let mut bytes = Vec::new();
+  response.copy_to(&mut bytes).map_err(|err| download_error(err.to_string()))?;
+  Ok(bytes)
+}
+
+#[cfg(not(feature = "url"))]
+fn download(url: &str) -> Result<Vec<u8>> {
+  Err(Error::Download {
+    url: String::from(url),
+    reason: String::from("built without the 'url' feature"),
+  })
+}
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
+
+fn check_size(size: u64, max_size: u64) -> Result<()> {
+  if size > max_size {
+    return Err(Error::TooLarge { size, limit: max_size });
+  }
+
+  Ok(())
+}
+
+// '-' stands for stdin, so the subs can be piped from tools like ffmpeg or mkvextract
+fn read_input<P>(file_name: P, max_size: u64) -> Result<Vec<u8>> where P: AsRef<Path> {
+  if let Some(url) = file_name.as_ref().to_str().filter(|name| is_url(name)) {
+    let bytes = download(url)?;
+    check_size(bytes.len() as u64, max_size)?;
+    return Ok(bytes);
+  }
+
+  if file_name.as_ref() == Path::new("-") {
+    // One byte over the limit is enough to tell the input is too large
+    let mut bytes = Vec::new();
+    std::io::stdin().take(max_size + 1).read_to_end(&mut bytes)?;
+    check_size(bytes.len() as u64, max_size)?;
+    return Ok(bytes);
+  }
+
+  read_file(file_name, max_size)
+}
+
+// The size is checked before reading, so a mistyped path to a huge file does not exhaust memory
+fn read_file<P>(file_name: P, max_size: u64) -> Result<Vec<u8>> where P: AsRef<Path> {
+  check_size(std::fs::metadata(&file_name)?.len(), max_size)?;
+  Ok(std::fs::read(file_name)?)
+}
+
+pub fn load_text_file<P>(file_name: P, max_size: u64) -> Result<String> where P: AsRef<Path> {
+  Ok(String::from_utf8(read_file(file_name, max_size)?)?)
+}
+
+pub fn load_subs_file<P>(file_name: P, max_size: u64, warnings: &mut Vec<String>) -> Result<String> where P: AsRef<Path> {
+  decode_subs_bytes(read_input(file_name, max_size)?, warnings)
+}
+
+fn decode_subs_bytes(bytes: Vec<u8>, warnings: &mut Vec<String>) -> Result<String> {
+  match String::from_utf8(bytes) {
+    Ok(text) => Ok(text),
+    Err(err) => {
+      warnings.push(String::from("Subs file is not valid UTF-8, invalid characters are replaced"));
+      Ok(String::from_utf8_lossy(err.as_bytes()).into_owned())
+    }
+  }
+}
+
+// The first encoding decoding the file without replacements into some subs wins,
+// if none does the file is read as UTF-8 with invalid characters replaced
+pub fn load_subs_file_with_encodings<P>(file_name: P, max_size: u64, encodings: &[&'static Encoding], warnings: &mut Vec<String>) -> Result<(String, &'static str)>
+  where P: AsRef<Path> {
+  let bytes = read_input(file_name, max_size)?;
+
+  for encoding in encodings.iter() {
+    if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(&bytes) {
+      let format = detect_format(&text).unwrap_or(SubsFormat::Srt);
+
+      if parse_subs(&convert_to_srt(&text, format)).is_ok_and(|subs| !subs.is_empty()) {
+        return Ok((text.into_owned(), encoding.name()));
+      }
+    }
+  }
+
+  warnings.push(String::from("None of the encodings fit the subs file, it is read as UTF-8"));
+  Ok((decode_subs_bytes(bytes, warnings)?, encoding_rs::UTF_8.name()))
+}
@@ -0,0 +1,74 @@
+use translate_core::*;
+
+use crate::log::warning;
+
+// A self-hosted or public LibreTranslate server, the endpoint is its base url like "http://localhost:5000"
+#[derive(Clone)]
+pub struct LibreTranslate {
+  endpoint: String,
+  #[cfg_attr(not(feature = "libretranslate"), allow(dead_code))]
+  api_key: Option<String>,
+  #[cfg(feature = "libretranslate")]
+  client: reqwest::Client,
+}
+
+impl LibreTranslate {
+  pub fn new(endpoint: &str, api_key: Option<&str>) -> LibreTranslate {
+    LibreTranslate {
+      endpoint: String::from(endpoint.trim_end_matches('/')),
+      api_key: api_key.map(String::from),
+      #[cfg(feature = "libretranslate")]
+      client: reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(60))
+        .build()
+        .expect("Failed to create the HTTP client"),
+    }
+  }
+}
+
+#[cfg(feature = "libretranslate")]
+impl ETranslate for LibreTranslate {
+  // Answers are {"translatedText": "..."} on success and {"error": "..."} with an error status otherwise
+  fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+    let source = source.to_string().to_lowercase();
+    let target = target.to_string().to_lowercase();
+    let mut params = vec![("q", text.as_str()), ("source", source.as_str()), ("target", target.as_str()), ("format", "text")];
+
+    if let Some(api_key) = &self.api_key {
+      params.push(("api_key", api_key));
+    }
+
+    let mut response = match self.client.post(&format!("{}/translate", self.endpoint)).form(&params).send() {
+      Ok(response) => response,
+      Err(err) => {
+        warning(&format!("LibreTranslate request failed: {}", err));
+        return None;
+      }
+    };
+
+    let status = response.status();
+    let body: serde_json::Value = match response.text().map(|text| serde_json::from_str(&text)) {
+      Ok(Ok(body)) => body,
+      _ => {
+        warning(&format!("LibreTranslate answered {} without valid JSON", status));
+        return None;
+      }
+    };
+
+    match (status.is_success(), body["translatedText"].as_str()) {
+      (true, Some(translation)) => Some(String::from(translation)),
+      _ => {
+        warning(&format!("LibreTranslate answered {}: {}", status, body["error"].as_str().unwrap_or("no translation in the answer")));
+        None
+      }
+    }
+  }
+}
+
+#[cfg(not(feature = "libretranslate"))]
+impl ETranslate for LibreTranslate {
+  fn translate(&self, _text: String, _source: Langage, _target: Langage) -> Option<String> {
+    warning(&format!("cannot reach '{}', built without the 'libretranslate' feature", self.endpoint));
+    None
+  }
+}
@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::trace::escape_string;
+
+static JSON_LOGS: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json_logs(enabled: bool) {
+  JSON_LOGS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn json_logs() -> bool {
+  JSON_LOGS.load(Ordering::Relaxed)
+}
+
+pub fn set_quiet(enabled: bool) {
+  QUIET.store(enabled, Ordering::Relaxed);
+}
+
+// Only errors are logged in quiet mode
+pub fn quiet() -> bool {
+  QUIET.load(Ordering::Relaxed)
+}
+
+// Events go to stderr, prefixed by their level for people or as one JSON object per line for tools
+pub fn log_event(level: &str, message: &str, file: Option<&str>, cue: Option<u32>) {
+  if quiet() && level != "error" {
+    return;
+  }
+
+  if json_logs() {
+    eprintln!("{{\"level\": {}, \"message\": {}, \"file\": {}, \"cue\": {}}}",
+              escape_string(level),
+              escape_string(message),
+              file.map_or(String::from("null"), escape_string),
+              cue.map_or(String::from("null"), |cue| cue.to_string()));
+    return;
+  }
+
+  match level {
+    "warning" => eprintln!("Warning: {}", message),
+    "error" => eprintln!("Error: {}", message),
+    _ => eprintln!("{}", message),
+  }
+}
+
+pub(crate) fn warning(message: &str) {
+  log_event("warning", message, None, None);
+}
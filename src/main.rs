@@ -1,74 +1,151 @@
-use std::collections::hash_map::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{Read, Write, Result};
+use std::io::{ErrorKind, Write, Result};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::thread::sleep;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use chrono::{Utc, NaiveTime};
+use chrono::Utc;
 use clap::{App, Arg};
+use encoding_rs::Encoding;
 use regex::Regex;
-use regex::Captures;
-use translate_core::*;
-use std::fmt;
+use srt_translator::*;
+
+// Set when the subs go to stdout, so progress messages do not mix with them
+static PROGRESS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+macro_rules! progress {
+  ($($arg:tt)*) => {
+    if quiet() {
+    } else if json_logs() {
+      log_event("info", &format!($($arg)*), None, None);
+    } else if PROGRESS_TO_STDERR.load(Ordering::Relaxed) {
+      eprintln!($($arg)*);
+    } else {
+      println!($($arg)*);
+    }
+  };
+}
+
+macro_rules! warning {
+  ($($arg:tt)*) => {
+    log_event("warning", &format!($($arg)*), None, None)
+  };
+}
+
+macro_rules! error {
+  ($($arg:tt)*) => {
+    log_event("error", &format!($($arg)*), None, None)
+  };
+}
 
 struct Args {
   input_subs_filename: String,
   output_subs_filename: String,
   database_filename: String,
   analyze_mode: bool,
+  dual_output: bool,
+  split_on: Option<Regex>,
+  replace_flagged: bool,
+  divider: Option<String>,
+  incremental_output: bool,
+  resume: bool,
+  timeout: Option<Duration>,
+  engine: Engine,
+  cache_filename: Option<String>,
+  refresh_cache: bool,
+  change_report_filename: Option<String>,
+  trace_filename: Option<String>,
+  parallel_filename: Option<String>,
+  parallel_layout: ParallelLayout,
+  preview_filename: Option<String>,
+  patch_filename: Option<String>,
+  passthrough_filename: Option<String>,
+  strict_mode: bool,
+  lenient: bool,
+  repair: bool,
+  deck_filename: Option<String>,
+  markdown_filename: Option<String>,
+  deck_delimiter: char,
+  pos_tag: bool,
+  deck_examples: bool,
+  studied_words: HashSet<String>,
+  force: bool,
+  write_metadata: bool,
+  backup_db: bool,
+  append_db: bool,
+  no_db: bool,
+  db_readonly: bool,
+  db_order: Vec<WordKind>,
+  db_kinds: Vec<WordKind>,
+  db_max_words: Option<usize>,
+  restore_db: bool,
+  reorder_mode: bool,
+  overlay_filename: Option<String>,
+  sort_by_time: bool,
+  renumber: bool,
+  min_gap: Option<chrono::Duration>,
+  decode_entities: bool,
+  max_cue_chars: Option<usize>,
+  filter_words: HashSet<String>,
+  max_cps: Option<f64>,
+  time_precision: usize,
+  count_only: bool,
+  profile: bool,
+  word_regex: Regex,
+  recursive: bool,
+  input_list: Option<String>,
+  output_dir: Option<String>,
+  encodings: Vec<&'static Encoding>,
+  output_encoding: Option<&'static Encoding>,
+  input_format: Option<SubsFormat>,
+  max_file_size: u64,
+  translate_options: TranslateOptions,
 }
 
-struct Sub {
-  index: u32,
-  start_time: NaiveTime,
-  end_time: NaiveTime,
-  text: String,
-  need_translation: bool,
+impl Args {
+  // Several input files share one database, a failed one does not stop the others
+  fn is_batch(&self) -> bool {
+    self.recursive || self.input_list.is_some()
+  }
 }
 
-enum WordKind {
-  Known,
-  Unknown,
-  New,
+fn prepare_output_dir(output_dir: &str) -> Result<()> {
+  std::fs::create_dir_all(output_dir)?;
+  let probe_filename = Path::new(output_dir).join(".srt-translator-probe");
+  File::create(&probe_filename)?;
+  std::fs::remove_file(&probe_filename)
 }
 
-impl FromStr for WordKind {
-  type Err = String;
-
-  fn from_str(s: &str) -> std::result::Result<WordKind, Self::Err> {
-    match s {
-      "k" => Ok(WordKind::Known),
-      "u" => Ok(WordKind::Unknown),
-      "?" => Ok(WordKind::New),
-      _ => Err(String::from("Parsing error"))
+// The database lives next to the executable, or in the working directory if the executable path is unknown
+fn default_database_filename() -> String {
+  let filename = match std::env::current_exe() {
+    Ok(exe_filename) => exe_filename.with_file_name("words.db"),
+    Err(err) => {
+      let filename = std::env::current_dir().unwrap_or_default().join("words.db");
+      warning!("failed to locate the executable ({}), using the default database '{}'", err, filename.display());
+      filename
     }
-  }
-}
+  };
 
-struct Word<'a> {
-  text: &'a str,
-  kind: WordKind,
+  filename.to_string_lossy().into_owned()
 }
 
-impl fmt::Display for Sub {
-  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{}\n{} --> {}\n{}\n",
-           self.index,
-           self.start_time.format("%H:%M:%S,%3f"),
-           self.end_time.format("%H:%M:%S,%3f"),
-           self.text)
-  }
-}
+// Every command line here must stay valid as the flags change
+const EXAMPLES: [(&str, &str); 8] = [
+  ("Fill the words database from a movie without translating it", "srt-translator -a movie.srt -d words.txt"),
+  ("Translate the subs having unknown or new words", "srt-translator movie.srt movie.out.srt -d words.txt"),
+  ("Translate German subs to English", "srt-translator film.srt -d words.de.txt --source-lang de --target-lang en"),
+  ("Translate all SRT files of a season sharing one database", "srt-translator -r season1/ -d words.txt --output-dir translated/"),
+  ("Translate the files listed in a manifest, one 'input[<TAB>output]' per line", "srt-translator --input-list files.txt -d words.txt"),
+  ("Export the unknown and new words to a study deck with example sentences", "srt-translator -a movie.srt -d words.txt --deck deck.tsv --deck-with-examples"),
+  ("Check the word counts of the subs without writing anything", "srt-translator movie.srt -d words.txt --count-only"),
+  ("Translate with a self-hosted LibreTranslate server, needs a build with the libretranslate feature", "srt-translator movie.srt -d words.txt --engine libretranslate --endpoint http://localhost:5000"),
+];
 
-impl Sub {
-  fn stringify(&self) -> String {
-    format!("{}\n{} --> {}\n{}\n\n",
-            self.index,
-            self.start_time.format("%H:%M:%S,%3f"),
-            self.end_time.format("%H:%M:%S,%3f"),
-            self.text)
+fn print_examples() {
+  for (description, command) in EXAMPLES.iter() {
+    println!("{}:\n  {}\n", description, command);
   }
 }
 
@@ -78,9 +155,9 @@ fn get_args() -> Args {
     .author("ZeuS <andy2002ua@gmail.com>")
     .about("Translate given subtitles file selectively using lists of known and unknown words")
     .arg(Arg::with_name("input")
-      .required(true)
+      .required_unless_one(&["restore-db", "input-list", "examples"])
       .value_name("INPUT SUBS")
-      .help("Sets an input subtitles file")
+      .help("Sets an input subtitles file, '-' for stdin, an http(s) url, or a directory with --recursive")
       .index(1))
     .arg(Arg::with_name("output")
       .short("o")
@@ -95,262 +172,1564 @@ fn get_args() -> Args {
       .value_name("DATABASE FILE")
       .takes_value(true)
       .help("Sets the database file"))
+    .arg(Arg::with_name("overlay")
+      .long("overlay")
+      .value_name("OVERLAY DATABASE FILE")
+      .takes_value(true)
+      .help("Sets the database file layered over the main one, which receives new words instead"))
     .arg(Arg::with_name("analyze")
       .short("a")
       .long("analyze")
       .help("Skip translation and feel words database"))
+    .arg(Arg::with_name("source-lang")
+      .long("source-lang")
+      .value_name("LANGUAGE")
+      .takes_value(true)
+      .default_value("en")
+      .validator(|code| parse_language(&code).map(|_| ()))
+      .help("Sets the language of the subs"))
+    .arg(Arg::with_name("target-lang")
+      .long("target-lang")
+      .value_name("LANGUAGE")
+      .takes_value(true)
+      .default_value("ru")
+      .validator(|code| parse_language(&code).map(|_| ()))
+      .help("Sets the language to translate the subs to"))
+    .arg(Arg::with_name("engine")
+      .long("engine")
+      .value_name("ENGINE")
+      .takes_value(true)
+      .possible_values(&["google", "libretranslate"])
+      .default_value("google")
+      .help("Sets the translation backend"))
+    .arg(Arg::with_name("endpoint")
+      .long("endpoint")
+      .value_name("URL")
+      .takes_value(true)
+      .required_if("engine", "libretranslate")
+      .help("Sets the base url of the LibreTranslate server, like http://localhost:5000"))
+    .arg(Arg::with_name("api-key")
+      .long("api-key")
+      .value_name("KEY")
+      .takes_value(true)
+      .requires("endpoint")
+      .help("Sets the API key sent to the LibreTranslate server"))
+    .arg(Arg::with_name("context")
+      .long("context")
+      .value_name("TEXT")
+      .takes_value(true)
+      .validator(|context| if context.trim().is_empty() || context.contains(['\r', '\n']) { Err(String::from("the context must be a single non-empty line")) } else { Ok(()) })
+      .help("Sends a TEXT line like \"This is movie dialogue:\" before each chunk to hint the domain, it is dropped from the translation"))
+    .arg(Arg::with_name("force-rtl")
+      .long("force-rtl")
+      .help("Mark translations as right to left text, no supported target language needs it yet"))
+    .arg(Arg::with_name("sentence-mode")
+      .long("sentence-mode")
+      .help("Translate whole sentences spanning several subs instead of each sub separately"))
+    .arg(Arg::with_name("divider")
+      .long("divider")
+      .value_name("TEXT")
+      .takes_value(true)
+      .allow_hyphen_values(true)
+      .conflicts_with("replace-flagged")
+      .validator(|divider| if !divider.is_empty() && !divider.contains(char::is_whitespace) { Ok(()) } else { Err(String::from("the divider must be non-empty and without spaces")) })
+      .help("Puts a TEXT line like '----' between the original and the translation of each sub"))
+    .arg(Arg::with_name("replace-flagged")
+      .long("replace-flagged")
+      .help("Write only the translation for subs needing translation and keep the other subs unchanged"))
+    .arg(Arg::with_name("incremental-output")
+      .long("incremental-output")
+      .help("Rewrite the output file after each translated chunk"))
+    .arg(Arg::with_name("resume")
+      .long("resume")
+      .help("Skips the files translated by an interrupted run, as recorded in '<INPUT SUBS>.progress'. Only files are recorded, so resuming an unfinished file at its next pending chunk needs --cache, which is saved after every chunk"))
+    .arg(Arg::with_name("dual-output")
+      .long("dual-output")
+      .help("Also write original and translated texts as two separate subtitles files"))
+    .arg(Arg::with_name("split-on")
+      .long("split-on")
+      .value_name("REGEX")
+      .takes_value(true)
+      .validator(|pattern| Regex::new(&pattern).map(|_| ()).map_err(|err| err.to_string()))
+      .help("Starts a new numbered output file at each sub matching REGEX, like chapter markers"))
+    .arg(Arg::with_name("highlight-color")
+      .long("highlight-color")
+      .value_name("COLOR")
+      .takes_value(true)
+      .default_value("yellow")
+      .validator(|color| parse_color(&color).map(|_| ()))
+      .help("Sets the color of unknown words as #RRGGBB or a color name"))
+    .arg(Arg::with_name("highlight-markup")
+      .long("highlight-markup")
+      .value_name("MARKUP")
+      .takes_value(true)
+      .possible_values(&["font", "span", "bold", "underline", "none"])
+      .default_value("font")
+      .help("Sets the markup of unknown words: font or span color, bold, underline or none"))
+    .arg(Arg::with_name("no-highlight")
+      .long("no-highlight")
+      .conflicts_with("highlight-markup")
+      .help("Leave unknown words unmarked, same as '--highlight-markup none'"))
+    .arg(Arg::with_name("change-report")
+      .long("change-report")
+      .value_name("REPORT FILE")
+      .takes_value(true)
+      .help("Writes indices of translated and untouched subs to the file instead of printing them"))
+    .arg(Arg::with_name("trace-json")
+      .long("trace-json")
+      .value_name("TRACE FILE")
+      .takes_value(true)
+      .help("Writes original text, translation and trigger words of each translated sub to a JSON file"))
+    .arg(Arg::with_name("parallel-text")
+      .long("parallel-text")
+      .value_name("TEXT FILE")
+      .takes_value(true)
+      .help("Writes the original and the translation of each translated sub as a line of plain text"))
+    .arg(Arg::with_name("parallel-layout")
+      .long("parallel-layout")
+      .value_name("LAYOUT")
+      .takes_value(true)
+      .possible_values(&["tsv", "aligned"])
+      .requires("parallel-text")
+      .help("Sets the parallel text columns: tab separated, the default, or aligned side by side"))
+    .arg(Arg::with_name("preview-html")
+      .long("preview-html")
+      .value_name("HTML FILE")
+      .takes_value(true)
+      .help("Writes original and translated subs side by side to an HTML file"))
+    .arg(Arg::with_name("patch")
+      .long("patch")
+      .value_name("PATCH FILE")
+      .takes_value(true)
+      .help("Writes the changes of each cue as a unified diff"))
+    .arg(Arg::with_name("passthrough-file")
+      .long("passthrough-file")
+      .value_name("SUBS FILE")
+      .takes_value(true)
+      .help("Writes subs left untranslated to a separate subtitles file for review"))
+    .arg(Arg::with_name("lenient")
+      .long("lenient")
+      .help("Find subs by their index and timing lines, for files missing blank lines between subs"))
+    .arg(Arg::with_name("repair")
+      .long("repair")
+      .help("Fix subs missing their index or timing line before parsing and report each fix"))
+    .arg(Arg::with_name("strict")
+      .long("strict")
+      .help("Fail on any subs parsing warning instead of ignoring it"))
+    .arg(Arg::with_name("output-dir")
+      .long("output-dir")
+      .value_name("OUTPUT DIR")
+      .takes_value(true)
+      .help("Sets the directory to place output subtitles files in"))
+    .arg(Arg::with_name("translate-all")
+      .long("translate-all")
+      .help("Translate all subs regardless of known words in the database"))
+    .arg(Arg::with_name("confidence-threshold")
+      .long("confidence-threshold")
+      .value_name("WEIGHT")
+      .takes_value(true)
+      .validator(|weight| weight.parse::<f32>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Treat known words with a weight below the threshold as unknown"))
+    .arg(Arg::with_name("min-unknown")
+      .long("min-unknown")
+      .value_name("COUNT")
+      .takes_value(true)
+      .default_value("1")
+      .validator(|count| count.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Sets the minimum number of unknown words in a sub to translate it"))
+    .arg(Arg::with_name("max-requests")
+      .long("max-requests")
+      .value_name("COUNT")
+      .takes_value(true)
+      .validator(|count| count.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Sets the maximum number of translation requests sent in one run"))
+    .arg(Arg::with_name("skip-failed-chunks")
+      .long("skip-failed-chunks")
+      .help("Leave the subs of a chunk failing after its retries untranslated instead of stopping"))
+    .arg(Arg::with_name("cache")
+      .long("cache")
+      .value_name("FILE")
+      .takes_value(true)
+      .help("Reuses translations of lines seen in earlier runs and saves the new ones to FILE"))
+    .arg(Arg::with_name("refresh-cache")
+      .long("refresh-cache")
+      .requires("cache")
+      .help("Translates every line anew but still saves the results to the cache, unlike leaving out --cache"))
+    .arg(Arg::with_name("timeout-secs")
+      .long("timeout-secs")
+      .value_name("SECONDS")
+      .takes_value(true)
+      .validator(|seconds| seconds.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Sets the time to wait for a translation request before retrying it"))
+    .arg(Arg::with_name("merge-short")
+      .long("merge-short")
+      .value_name("CHARS")
+      .takes_value(true)
+      .validator(|chars| chars.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Translate subs shorter than CHARS together with a neighbor sub for context"))
+    .arg(Arg::with_name("no-normalize")
+      .long("no-normalize")
+      .help("Keep repeated and trailing spaces in subs text as is"))
+    .arg(Arg::with_name("db-export-md")
+      .long("db-export-md")
+      .value_name("MD FILE")
+      .takes_value(true)
+      .help("Writes the database words grouped by kind to a Markdown file"))
+    .arg(Arg::with_name("deck")
+      .long("deck")
+      .value_name("DECK FILE")
+      .takes_value(true)
+      .help("Writes unknown and new words found in the subs to a study deck file"))
+    .arg(Arg::with_name("deck-delimiter")
+      .long("deck-delimiter")
+      .value_name("DELIMITER")
+      .takes_value(true)
+      .possible_values(&["tab", "comma", "semicolon"])
+      .default_value("tab")
+      .help("Sets the field separator of the study deck file"))
+    .arg(Arg::with_name("pos-tag")
+      .long("pos-tag")
+      .requires("deck")
+      .help("Adds a guessed part of speech of each word to the study deck"))
+    .arg(Arg::with_name("deck-with-examples")
+      .long("deck-with-examples")
+      .requires("deck")
+      .help("Adds the first sub using each word to the study deck"))
+    .arg(Arg::with_name("studied-list")
+      .long("studied-list")
+      .value_name("STUDIED FILE")
+      .takes_value(true)
+      .requires("deck")
+      .help("Leaves the words of this file out of the study deck, one per line or an earlier deck file"))
+    .arg(Arg::with_name("force")
+      .long("force")
+      .help("Process the input even if it looks already translated by this tool"))
+    .arg(Arg::with_name("no-metadata")
+      .long("no-metadata")
+      .help("Do not write the sub with translation details at the start of output files"))
+    .arg(Arg::with_name("backup")
+      .long("backup")
+      .help("Copy the database file to a .bak file before updating it"))
+    .arg(Arg::with_name("append-db")
+      .long("append-db")
+      .help("Append new words to the end of the database file keeping existing lines unchanged"))
+    .arg(Arg::with_name("db-order")
+      .long("db-order")
+      .value_name("KINDS")
+      .takes_value(true)
+      .validator(|order| parse_db_order(&order).map(|_| ()))
+      .help("Sets the order of word kinds in the database like '?uk', 'none' sorts all words alphabetically"))
+    .arg(Arg::with_name("db-kinds")
+      .long("db-kinds")
+      .value_name("KINDS")
+      .takes_value(true)
+      .validator(|kinds| parse_db_kinds(&kinds).map(|_| ()))
+      .help("Sets comma separated word kinds written to the database like '?,u', all kinds by default"))
+    .arg(Arg::with_name("db-max-words")
+      .long("db-max-words")
+      .value_name("COUNT")
+      .takes_value(true)
+      .validator(|count| count.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Sets the maximum number of words in the database, only the least frequent new words are dropped"))
+    .arg(Arg::with_name("no-db")
+      .long("no-db")
+      .conflicts_with_all(&["database", "overlay", "append-db", "backup", "analyze", "count-only"])
+      .help("Translate all subs without reading or writing the words database"))
+    .arg(Arg::with_name("db-readonly")
+      .long("db-readonly")
+      .conflicts_with_all(&["no-db", "append-db", "backup", "analyze"])
+      .help("Use the words database to translate but never write to it"))
+    .arg(Arg::with_name("restore-db")
+      .long("restore-db")
+      .help("Restore the database file from its .bak file and exit"))
+    .arg(Arg::with_name("match")
+      .long("match")
+      .value_name("REGEX")
+      .takes_value(true)
+      .validator(|pattern| Regex::new(&pattern).map(|_| ()).map_err(|err| err.to_string()))
+      .help("Translate only subs with text matching the regular expression"))
+    .arg(Arg::with_name("filter-word")
+      .long("filter-word")
+      .value_name("WORD")
+      .takes_value(true)
+      .multiple(true)
+      .number_of_values(1)
+      .help("Write only the subs having WORD, can be given several times to keep the subs having any of them"))
+    .arg(Arg::with_name("index-from")
+      .long("index-from")
+      .value_name("INDEX")
+      .takes_value(true)
+      .validator(|index| index.parse::<u32>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Translate only subs with an index of at least INDEX"))
+    .arg(Arg::with_name("index-to")
+      .long("index-to")
+      .value_name("INDEX")
+      .takes_value(true)
+      .validator(|index| index.parse::<u32>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Translate only subs with an index of at most INDEX"))
+    .arg(Arg::with_name("reorder")
+      .long("reorder")
+      .help("Swap original and translated text in a file translated by this tool and exit"))
+    .arg(Arg::with_name("sort-by-time")
+      .long("sort-by-time")
+      .help("Sort subs by their start time"))
+    .arg(Arg::with_name("time-precision")
+      .long("time-precision")
+      .value_name("DIGITS")
+      .takes_value(true)
+      .possible_values(&["0", "1", "2", "3"])
+      .help("Sets the number of fractional second digits of output timings, 2 for ASS, defaults to 3"))
+    .arg(Arg::with_name("max-cue-chars")
+      .long("max-cue-chars")
+      .value_name("CHARS")
+      .takes_value(true)
+      .validator(|chars| chars.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Split translated subs longer than CHARS into two and renumber all subs"))
+    .arg(Arg::with_name("reading-speed")
+      .long("reading-speed")
+      .help("Report the output subs having more chars per second than --max-cps"))
+    .arg(Arg::with_name("max-cps")
+      .long("max-cps")
+      .value_name("CPS")
+      .takes_value(true)
+      .requires("reading-speed")
+      .validator(|cps| cps.parse::<f64>().map_err(|err| err.to_string()).and_then(|cps| if cps > 0.0 { Ok(()) } else { Err(String::from("must be positive")) }))
+      .help("Sets the fastest reading speed for --reading-speed, 20 chars per second by default"))
+    .arg(Arg::with_name("decode-entities")
+      .long("decode-entities")
+      .help("Decode HTML entities like &amp; or &#233; in the subs text"))
+    .arg(Arg::with_name("min-gap-ms")
+      .long("min-gap-ms")
+      .value_name("MS")
+      .takes_value(true)
+      .validator(|ms| ms.parse::<u32>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Trims the end of subs so at least MS milliseconds pass before the next sub starts"))
+    .arg(Arg::with_name("renumber")
+      .long("renumber")
+      .help("Renumber subs sequentially starting from 1"))
+    .arg(Arg::with_name("stopwords")
+      .long("stopwords")
+      .value_name("STOPWORDS FILE")
+      .takes_value(true)
+      .help("Sets the file of words never added to the database nor translated, 'en' for the built-in English list"))
+    .arg(Arg::with_name("skip-acronyms")
+      .long("skip-acronyms")
+      .help("Keep all caps words like NASA out of the database and never translate them"))
+    .arg(Arg::with_name("word-notes")
+      .long("word-notes")
+      .help("Add the translation of each unknown word of a sub after its translation"))
+    .arg(Arg::with_name("ruby")
+      .long("ruby")
+      .conflicts_with("word-notes")
+      .help("Put the translation of each unknown word over it as <ruby> markup instead of translating the subs"))
+    .arg(Arg::with_name("gloss-format")
+      .long("gloss-format")
+      .value_name("FORMAT")
+      .takes_value(true)
+      .conflicts_with_all(&["word-notes", "ruby"])
+      .validator(|format| parse_gloss_format(&format).map(|_| ()))
+      .help("Put the translation of each unknown word next to it as the format tells like \"{word} ({gloss})\" instead of translating the subs"))
+    .arg(Arg::with_name("case-locale")
+      .long("case-locale")
+      .value_name("LOCALE")
+      .takes_value(true)
+      .possible_values(&["default", "tr", "de"])
+      .default_value("default")
+      .help("Sets the locale lowercasing words to compare them with the database: Turkish dotless i or German 'ß' as \"ss\""))
+    .arg(Arg::with_name("word-regex")
+      .long("word-regex")
+      .value_name("REGEX")
+      .takes_value(true)
+      .validator(|pattern| Regex::new(&pattern).map(|_| ()).map_err(|err| err.to_string()))
+      .help("Sets the regular expression finding words in the lowercased subs, its 'word' group is the word if present. Words are made of Unicode letters by default, \"(?msx)(?:(?P<word>[a-z']+?)[^a-z']+)\" keeps to ASCII ones"))
+    .arg(Arg::with_name("dialogue-dashes")
+      .long("dialogue-dashes")
+      .help("Keep each dash of a dialogue sub at the start of its own line in the output"))
+    .arg(Arg::with_name("explain")
+      .long("explain")
+      .help("Prints the words of each sub with their kinds and why the sub is translated or not"))
+    .arg(Arg::with_name("detect-skip")
+      .long("detect-skip")
+      .help("Do not translate subs that look like they are in the target language already"))
+    .arg(Arg::with_name("translate-urls")
+      .long("translate-urls")
+      .help("Treat URLs, emails, file names and hashtags as regular text"))
+    .arg(Arg::with_name("force-karaoke-translate")
+      .long("force-karaoke-translate")
+      .help("Translate subs with per word karaoke timing tags instead of keeping them verbatim"))
+    .arg(Arg::with_name("strip-ass-tags")
+      .long("strip-ass-tags")
+      .help("Remove ASS override tags like {\\an8} from the subs instead of keeping them"))
+    .arg(Arg::with_name("profile")
+      .long("profile")
+      .help("Print the time spent in each phase of the run"))
+    .arg(Arg::with_name("count-only")
+      .long("count-only")
+      .help("Print known, unknown and new word counts of the subs and exit without writing anything"))
+    .arg(Arg::with_name("max-file-size")
+      .long("max-file-size")
+      .value_name("MEGABYTES")
+      .takes_value(true)
+      .validator(|megabytes| megabytes.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+      .help("Sets the largest file read, 100 MB by default"))
+    .arg(Arg::with_name("output-encoding")
+      .long("output-encoding")
+      .value_name("ENCODING")
+      .takes_value(true)
+      .validator(|label| Encoding::for_label(label.trim().as_bytes()).map(|_| ()).ok_or(format!("Unknown encoding '{}'", label)))
+      .help("Sets the encoding of the output subs, the one the input was decoded with by default"))
+    .arg(Arg::with_name("input-format")
+      .long("input-format")
+      .value_name("FORMAT")
+      .takes_value(true)
+      .possible_values(&["srt", "vtt", "ass"])
+      .help("Sets the subs format instead of telling it by the file content"))
+    .arg(Arg::with_name("encoding-fallback")
+      .long("encoding-fallback")
+      .value_name("ENCODINGS")
+      .takes_value(true)
+      .validator(|labels| labels.split(',')
+        .find(|label| Encoding::for_label(label.trim().as_bytes()).is_none())
+        .map_or(Ok(()), |label| Err(format!("Unknown encoding '{}'", label))))
+      .help("Sets comma separated encodings tried in order to decode each subs file"))
+    .arg(Arg::with_name("recursive")
+      .short("r")
+      .long("recursive")
+      .help("Translate all SRT and WebVTT files in the input directory and its subdirectories sharing one database"))
+    .arg(Arg::with_name("input-list")
+      .long("input-list")
+      .value_name("MANIFEST")
+      .takes_value(true)
+      .conflicts_with_all(&["input", "recursive", "output"])
+      .help("Translate the files named one per line in MANIFEST sharing one database, a tab and an output path may follow each name"))
+    .arg(Arg::with_name("previous-output")
+      .long("previous-output")
+      .value_name("SUBS FILE")
+      .takes_value(true)
+      .conflicts_with_all(&["recursive", "input-list"])
+      .help("Reuses the translations of an earlier output for subs whose text and timing did not change"))
+    .arg(Arg::with_name("json-logs")
+      .long("json-logs")
+      .help("Writes progress, warnings and errors to stderr as one JSON object per line"))
+    .arg(Arg::with_name("examples")
+      .long("examples")
+      .help("Prints example command lines for common tasks and exits"))
+    .arg(Arg::with_name("quiet")
+      .short("q")
+      .long("quiet")
+      .conflicts_with("count-only")
+      .help("Prints nothing but errors"))
     .get_matches();
 
-  let input_subs_filename = matches.value_of("input").unwrap().to_owned();
+  if matches.is_present("examples") {
+    print_examples();
+    std::process::exit(0);
+  }
+
+  set_json_logs(matches.is_present("json-logs"));
+  set_quiet(matches.is_present("quiet"));
+
+  let input_subs_filename = matches.value_of("input").unwrap_or_default().to_owned();
+  let reorder_mode = matches.is_present("reorder");
   let mut input_file_path;
 
-  let output_subs_filename = match matches.value_of("output") {
+  let mut output_subs_filename = match matches.value_of("output") {
     Some(name) => name.to_owned(),
+    None if input_subs_filename == "-" => String::from("-"),
     None => {
-      input_file_path = PathBuf::from(&input_subs_filename);
-      input_file_path.set_extension("out.srt");
+      // Downloaded subs are saved in the working directory under the last part of the url
+      input_file_path = if is_url(&input_subs_filename) {
+        let url_path = input_subs_filename.split(['?', '#']).next().unwrap();
+        PathBuf::from(url_path.rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("subs.srt"))
+      } else {
+        PathBuf::from(&input_subs_filename)
+      };
+      input_file_path.set_extension(if reorder_mode { "reordered.srt" } else { "out.srt" });
       input_file_path.to_str().unwrap().to_owned()
     }
   };
 
+  if matches.is_present("split-on") && output_subs_filename == "-" {
+    error!("--split-on writes several files and cannot write to stdout");
+    std::process::exit(1);
+  }
+
+  if matches.is_present("incremental-output") && output_subs_filename == "-" {
+    error!("--incremental-output rewrites the output after each chunk and cannot write to stdout");
+    std::process::exit(1);
+  }
+
+  if let Some(output_dir) = matches.value_of("output-dir") {
+    if let Err(err) = prepare_output_dir(output_dir) {
+      error!("output directory '{}' is not writable: {}", output_dir, err);
+      std::process::exit(1);
+    }
+
+    if let Some(output_file_name) = Path::new(&output_subs_filename).file_name() {
+      output_subs_filename = Path::new(output_dir).join(output_file_name).to_str().unwrap().to_owned();
+    }
+  }
+
   let database_filename = match matches.value_of("database") {
     Some(name) => name.to_owned(),
-    None => {
-      let mut filename = std::env::current_exe().unwrap();
-      filename.set_file_name("words.db");
-      String::from(filename.to_str().unwrap())
-    }
+    None => default_database_filename(),
   };
 
   let analyze_mode = matches.is_present("analyze");
+  let source_language = parse_language(matches.value_of("source-lang").unwrap()).unwrap();
+  let target_language = parse_language(matches.value_of("target-lang").unwrap()).unwrap();
+  let sentence_mode = matches.is_present("sentence-mode");
+  let dual_output = matches.is_present("dual-output");
+  let split_on = matches.value_of("split-on").map(|pattern| Regex::new(pattern).unwrap());
+  let replace_flagged = matches.is_present("replace-flagged");
+  let divider = matches.value_of("divider").map(String::from);
+  let incremental_output = matches.is_present("incremental-output");
+  let resume = matches.is_present("resume");
+  let timeout = matches.value_of("timeout-secs").map(|seconds| Duration::from_secs(seconds.parse().unwrap()));
+  let engine = match matches.value_of("engine").unwrap() {
+    "libretranslate" if !cfg!(feature = "libretranslate") => {
+      error!("the LibreTranslate engine needs a build with the 'libretranslate' feature");
+      std::process::exit(1);
+    }
+    "libretranslate" => Engine::LibreTranslate(LibreTranslate::new(matches.value_of("endpoint").unwrap(), matches.value_of("api-key"))),
+    _ => Engine::Google,
+  };
+  let cache_filename = matches.value_of("cache").map(String::from);
+  let refresh_cache = matches.is_present("refresh-cache");
+  let highlight_markup = if matches.is_present("no-highlight") {
+    HighlightMarkup::None
+  } else {
+    matches.value_of("highlight-markup").unwrap().parse().unwrap()
+  };
+  let change_report_filename = matches.value_of("change-report").map(String::from);
+  let trace_filename = matches.value_of("trace-json").map(String::from);
+  let parallel_filename = matches.value_of("parallel-text").map(String::from);
+  let parallel_layout = matches.value_of("parallel-layout").map_or(ParallelLayout::Tsv, |layout| layout.parse().unwrap());
+  let preview_filename = matches.value_of("preview-html").map(String::from);
+  let patch_filename = matches.value_of("patch").map(String::from);
+  let passthrough_filename = matches.value_of("passthrough-file").map(String::from);
+  let strict_mode = matches.is_present("strict");
+  let lenient = matches.is_present("lenient");
+  let repair = matches.is_present("repair");
+  let translate_all = matches.is_present("translate-all") || matches.is_present("no-db");
+  let confidence_threshold = matches.value_of("confidence-threshold").map(|weight| weight.parse().unwrap());
+  let min_unknown = matches.value_of("min-unknown").unwrap().parse().unwrap();
+  let max_requests = matches.value_of("max-requests").map(|count| count.parse().unwrap());
+  let merge_short = matches.value_of("merge-short").map(|chars| chars.parse().unwrap());
+  let normalize_text = !matches.is_present("no-normalize");
+  let deck_filename = matches.value_of("deck").map(String::from);
+  let markdown_filename = matches.value_of("db-export-md").map(String::from);
+  let pos_tag = matches.is_present("pos-tag");
+  let deck_examples = matches.is_present("deck-with-examples");
+  let force = matches.is_present("force");
+  let write_metadata = !matches.is_present("no-metadata");
+  let backup_db = matches.is_present("backup");
+  let append_db = matches.is_present("append-db");
+  let no_db = matches.is_present("no-db");
+  let db_readonly = matches.is_present("db-readonly");
+  let db_kinds = matches.value_of("db-kinds").map_or(DEFAULT_DB_ORDER.to_vec(), |kinds| parse_db_kinds(kinds).unwrap());
+  let db_max_words = matches.value_of("db-max-words").map(|count| count.parse().unwrap());
+  let db_order = matches.value_of("db-order").map_or(DEFAULT_DB_ORDER.to_vec(), |order| parse_db_order(order).unwrap());
+  let restore_db = matches.is_present("restore-db");
+  let overlay_filename = matches.value_of("overlay").map(String::from);
+  let sort_by_time = matches.is_present("sort-by-time");
+  let renumber = matches.is_present("renumber");
+  let min_gap = matches.value_of("min-gap-ms").map(|ms| chrono::Duration::milliseconds(ms.parse().unwrap()));
+  let decode_entities = matches.is_present("decode-entities");
+  let max_cue_chars = matches.value_of("max-cue-chars").map(|chars| chars.parse().unwrap());
+  let max_cps = if matches.is_present("reading-speed") {
+    Some(matches.value_of("max-cps").map_or(DEFAULT_MAX_CPS, |cps| cps.parse().unwrap()))
+  } else {
+    None
+  };
+  let time_precision = matches.value_of("time-precision").map_or(DEFAULT_TIME_PRECISION, |digits| digits.parse().unwrap());
+  let count_only = matches.is_present("count-only");
+  let profile = matches.is_present("profile");
+  let case_locale = matches.value_of("case-locale").unwrap().parse().unwrap();
+  let filter_words = matches.values_of("filter-word").map_or(HashSet::new(), |words| {
+    words.map(|word| fold_case(word.trim(), source_language, case_locale)).collect()
+  });
+  let word_regex = matches.value_of("word-regex").map_or_else(|| language_word_regex(source_language, case_locale), |pattern| Regex::new(pattern).unwrap());
+  let skip_acronyms = matches.is_present("skip-acronyms");
+  let word_notes = matches.is_present("word-notes");
+  let gloss_format = match matches.value_of("gloss-format") {
+    None if matches.is_present("ruby") => Some(RUBY_GLOSS_FORMAT),
+    gloss_format => gloss_format,
+  };
+  let dialogue_dashes = matches.is_present("dialogue-dashes");
+  let translate_urls = matches.is_present("translate-urls");
+  let context = match matches.value_of("context") {
+    Some(_) if !engine.supports_context() => {
+      warning!("the {} engine ignores --context, it is not sent", engine.name());
+      None
+    }
+    context => context,
+  };
+  let rtl_translation = matches.is_present("force-rtl");
+  let strip_ass_tags = matches.is_present("strip-ass-tags");
+  let translate_karaoke = matches.is_present("force-karaoke-translate");
+  let detect_skip = matches.is_present("detect-skip");
+  let explain = matches.is_present("explain");
+  let recursive = matches.is_present("recursive");
+  let input_list = matches.value_of("input-list").map(String::from);
+
+  if recursive && !Path::new(&input_subs_filename).is_dir() {
+    error!("'{}' is not a directory, --recursive needs a directory input", input_subs_filename);
+    std::process::exit(1);
+  }
+
+  let output_dir = matches.value_of("output-dir").map(String::from);
+  let max_file_size = matches.value_of("max-file-size").map_or(DEFAULT_MAX_FILE_SIZE, |megabytes| megabytes.parse::<u64>().unwrap() * 1024 * 1024);
+  let encodings = matches.value_of("encoding-fallback").map_or(Vec::new(), |labels| {
+    labels.split(',').map(|label| Encoding::for_label(label.trim().as_bytes()).unwrap()).collect()
+  });
+  let output_encoding = matches.value_of("output-encoding").map(|label| Encoding::for_label(label.trim().as_bytes()).unwrap());
+  let input_format = matches.value_of("input-format").map(|format| format.parse().unwrap());
+
+  let stopwords = match matches.value_of("stopwords") {
+    Some("en") => ENGLISH_STOPWORDS.iter().map(|&word| String::from(word)).collect(),
+    Some(filename) => parse_stopwords(&load_text_file(filename, max_file_size).unwrap_or_else(|err| exit_with_error(err))),
+    None => HashSet::new(),
+  };
+  // Output that cannot be matched is only a warning, every sub is translated then
+  let previous_cues = matches.value_of("previous-output").map_or_else(HashMap::new, |filename| {
+    load_text_file(filename, max_file_size)
+      .and_then(|text| parse_subs(&text))
+      .and_then(|previous_subs| parse_previous_cues(&previous_subs))
+      .unwrap_or_else(|err| {
+        warning!("previous output '{}' is not usable, all subs are translated: {}", filename, err);
+        HashMap::new()
+      })
+  });
+  let text_filter = matches.value_of("match").map(|pattern| Regex::new(pattern).unwrap());
+  let index_from = matches.value_of("index-from").map(|index| index.parse().unwrap());
+  let index_to = matches.value_of("index-to").map(|index| index.parse().unwrap());
+
+  let deck_delimiter = match matches.value_of("deck-delimiter").unwrap() {
+    "comma" => ',',
+    "semicolon" => ';',
+    _ => '\t',
+  };
+  // The first field of a deck line is its word, so earlier decks can be given as they are
+  let studied_words = matches.value_of("studied-list").map_or_else(HashSet::new, |filename| {
+    parse_stopwords(&load_text_file(filename, max_file_size).unwrap_or_else(|err| exit_with_error(err)))
+      .iter()
+      .map(|line| line.split(deck_delimiter).next().unwrap().trim_matches('"'))
+      .map(|word| fold_case(word, source_language, case_locale))
+      .collect()
+  });
 
   Args {
     input_subs_filename,
     output_subs_filename,
     database_filename,
     analyze_mode,
+    dual_output,
+    split_on,
+    replace_flagged,
+    divider,
+    incremental_output,
+    resume,
+    timeout,
+    engine,
+    cache_filename,
+    refresh_cache,
+    change_report_filename,
+    trace_filename,
+    parallel_filename,
+    parallel_layout,
+    preview_filename,
+    patch_filename,
+    passthrough_filename,
+    strict_mode,
+    lenient,
+    repair,
+    deck_filename,
+    markdown_filename,
+    deck_delimiter,
+    pos_tag,
+    deck_examples,
+    studied_words,
+    force,
+    write_metadata,
+    backup_db,
+    append_db,
+    no_db,
+    db_readonly,
+    db_order,
+    db_kinds,
+    db_max_words,
+    restore_db,
+    reorder_mode,
+    overlay_filename,
+    sort_by_time,
+    renumber,
+    min_gap,
+    decode_entities,
+    max_cue_chars,
+    filter_words,
+    max_cps,
+    time_precision,
+    count_only,
+    profile,
+    word_regex,
+    recursive,
+    input_list,
+    output_dir,
+    encodings,
+    output_encoding,
+    input_format,
+    max_file_size,
+    // The validators have checked the values already
+    translate_options: TranslateOptions::builder(source_language, target_language)
+      .sentence_mode(sentence_mode)
+      .highlight_color(matches.value_of("highlight-color").unwrap()).unwrap()
+      .highlight_markup(highlight_markup)
+      .translate_all(translate_all)
+      .confidence_threshold(confidence_threshold)
+      .min_unknown(min_unknown)
+      .normalize_text(normalize_text)
+      .max_requests(max_requests)
+      .skip_failed_chunks(matches.is_present("skip-failed-chunks"))
+      .merge_short(merge_short)
+      .text_filter(text_filter)
+      .index_from(index_from)
+      .index_to(index_to)
+      .stopwords(stopwords)
+      .skip_acronyms(skip_acronyms)
+      .word_notes(word_notes)
+      .gloss_format(gloss_format)
+      .dialogue_dashes(dialogue_dashes)
+      .translate_urls(translate_urls)
+      .context(context)
+      .translate_karaoke(translate_karaoke)
+      .rtl_translation(rtl_translation)
+      .strip_ass_tags(strip_ass_tags)
+      .detect_skip(detect_skip)
+      .explain(explain)
+      .case_locale(case_locale)
+      .previous_cues(previous_cues)
+      .build(),
+  }
+}
+
+fn save_text_file<P>(file_name: P, text: &str) where P: AsRef<Path> {
+  save_bytes_file(file_name, text.as_bytes());
+}
+
+fn save_bytes_file<P>(file_name: P, bytes: &[u8]) where P: AsRef<Path> {
+  if file_name.as_ref() == Path::new("-") {
+    std::io::stdout().write_all(bytes)
+      .expect("Failed to write to stdout");
+    return;
+  }
+
+  let mut output_file = File::create(file_name)
+    .expect("Failed to open file for writing");
+
+  output_file.write_all(bytes)
+    .expect("Failed to write to the file");
+}
+
+// Characters the encoding lacks are written as HTML numeric references like "&#1078;"
+fn save_subs<P, F>(file_name: P, header: &str, subs: &[Sub], time_precision: usize, encoding: &'static Encoding, stringify: F) where P: AsRef<Path>, F: Fn(&Sub, usize) -> String {
+  let subs_text = subs.iter().fold(String::from(header), |acc, sub| acc + &stringify(sub, time_precision));
+  save_encoded_text_file(file_name, &subs_text, encoding);
+}
+
+// Subtitles files are written in the encoding of the main output, whatever part of the subs they have
+fn save_encoded_text_file<P>(file_name: P, text: &str, encoding: &'static Encoding) where P: AsRef<Path> {
+  let (bytes, output_encoding, had_unmappable) = encoding.encode(text);
+
+  if had_unmappable {
+    warning!("some characters cannot be written in {}, they are written as numeric references", output_encoding.name());
+  }
+
+  save_bytes_file(file_name, &bytes);
+}
+
+fn build_change_report(subs: &[Sub]) -> String {
+  // Subs of skipped or failed chunks are flagged but not translated, so they are passed through
+  let join_indices = |translated: bool| {
+    subs.iter()
+      .filter(|sub| sub.is_translated() == translated)
+      .map(|sub| sub.index.to_string())
+      .collect::<Vec<_>>()
+      .join(", ")
+  };
+
+  format!("Translated: {}\nPassed through: {}\n", join_indices(true), join_indices(false))
+}
+
+// Piped and downloaded input has no place for the progress file next to it
+fn progress_filename(input_subs_filename: &str) -> Option<String> {
+  if input_subs_filename == "-" || is_url(input_subs_filename) {
+    return None;
   }
+
+  Some(format!("{}.progress", input_subs_filename.trim_end_matches(['/', '\\'])))
 }
 
-fn load_text_file<P>(file_name: P) -> Result<String> where P: AsRef<Path> {
-  let mut text = String::new();
-  let mut input_file = File::open(file_name)?;
-  input_file.read_to_string(&mut text)?;
+fn load_db_text(filename: &str, max_size: u64) -> String {
+  match load_text_file(filename, max_size) {
+    Ok(text) => text,
+    Err(Error::Io(ref err)) if err.kind() == ErrorKind::NotFound => String::new(),
+    Err(err) => exit_with_error(err),
+  }
+}
 
-  Ok(text)
+fn backup_filename(database_filename: &str) -> String {
+  format!("{}.bak", database_filename)
 }
 
-fn parse_subs(text: &String) -> Vec<Sub> {
-  let mut subs = Vec::new();
+fn confirm(question: &str) -> bool {
+  print!("{} [y/N] ", question);
+  std::io::stdout().flush().expect("Failed to write to stdout");
 
-  let re = Regex::new(r"(?msx)
-        (?P<index>\d+)\r?\n
-        (?P<start_time>\d{2}:\d{2}:\d{2},\d{3})\s-->\s(?P<end_time>\d{2}:\d{2}:\d{2},\d{3})\r?\n
-        (?P<text>.+?)\r?\n\r?\n
-    ").unwrap();
+  let mut answer = String::new();
+  std::io::stdin().read_line(&mut answer).expect("Failed to read from stdin");
 
-  for caps in re.captures_iter(text.as_str()) {
-    let index: u32 = caps.name("index").unwrap().as_str().parse().unwrap();
-    let start_time = NaiveTime::parse_from_str(caps.name("start_time").unwrap().as_str(), "%H:%M:%S,%3f").unwrap();
-    let end_time = NaiveTime::parse_from_str(caps.name("end_time").unwrap().as_str(), "%H:%M:%S,%3f").unwrap();
-    let text = caps.name("text").unwrap().as_str().to_owned();
+  answer.trim().eq_ignore_ascii_case("y")
+}
+
+fn restore_database(args: &Args) -> Result<()> {
+  let backup_filename = backup_filename(&args.database_filename);
 
-    subs.push(Sub {
-      index,
-      start_time,
-      end_time,
-      text,
-      need_translation: false,
-    });
+  match std::fs::metadata(&backup_filename) {
+    Ok(metadata) if metadata.len() > 0 => {}
+    Ok(_) => return Err(std::io::Error::new(ErrorKind::InvalidData, format!("backup '{}' is empty", backup_filename))),
+    Err(err) => return Err(std::io::Error::new(err.kind(), format!("backup '{}' is not available: {}", backup_filename, err))),
   }
 
-  subs
+  if !args.force && !confirm(&format!("Overwrite '{}' with '{}'?", args.database_filename, backup_filename)) {
+    progress!("Database is not restored");
+    return Ok(());
+  }
+
+  std::fs::copy(&backup_filename, &args.database_filename)?;
+  progress!("Database '{}' is restored from '{}'", args.database_filename, backup_filename);
+
+  Ok(())
 }
 
-fn parse_db_words(text: &String) -> HashMap<&str, Word> {
-  let mut words = HashMap::new();
-  // TODO: make 're' const
-  let re = Regex::new(r"(?P<type>[\?ku]):(?P<text>.+?)\r?\n").unwrap();
+fn reorder_subs_file(args: &Args) -> srt_translator::Result<()> {
+  progress!("Read translated subs from: '{}'", &args.input_subs_filename);
+  let subs_text = load_text_file(&args.input_subs_filename, args.max_file_size)?;
+  let mut subs = parse_subs(&subs_text)?;
+  reorder_translations(&mut subs)?;
 
-  // TODO: replace by functional 'map' if possible
-  for caps in re.captures_iter(text.as_str()) {
-    let kind: WordKind = caps.name("type").unwrap().as_str().parse().unwrap();
-    let text = caps.name("text").unwrap().as_str();
+  progress!("Write reordered subs to: '{}'", &args.output_subs_filename);
+  save_subs(&args.output_subs_filename, "", &subs, args.time_precision, args.output_encoding.unwrap_or(encoding_rs::UTF_8), Sub::stringify);
 
-    words.insert(text, Word {
-      text,
-      kind,
-    });
+  Ok(())
+}
+
+fn print_word_counts(lowercase_subs_text: &str, word_regex: &Regex, db_words: &HashMap<&str, Word>,
+                     overlay_words: &HashMap<&str, Word>, stopwords: &HashSet<String>) {
+  // Indices are known, unknown and new, each counted as unique words and as occurrences
+  let mut unique = [0; 3];
+  let mut occurrences = [0; 3];
+
+  for (text, count) in count_sub_words(lowercase_subs_text, word_regex) {
+    if stopwords.contains(text) {
+      continue;
+    }
+
+    let kind = overlay_words.get(text).or_else(|| db_words.get(text)).map(|word| &word.kind);
+
+    let i = match kind {
+      Some(WordKind::Known) => 0,
+      Some(WordKind::Unknown) => 1,
+      _ => 2,
+    };
+
+    unique[i] += 1;
+    occurrences[i] += count;
   }
 
-  words
+  let total_occurrences: usize = occurrences.iter().sum();
+  let coverage = if total_occurrences > 0 { occurrences[0] as f64 * 100.0 / total_occurrences as f64 } else { 100.0 };
+
+  progress!("Known words: {} unique, {} occurrences", unique[0], occurrences[0]);
+  progress!("Unknown words: {} unique, {} occurrences", unique[1], occurrences[1]);
+  progress!("New words: {} unique, {} occurrences", unique[2], occurrences[2]);
+  progress!("Coverage: {:.1}%", coverage);
 }
 
-fn parse_sub_words(lowercase_subs_text: &String) -> HashMap<&str, Word> {
-  let mut sub_words: HashMap<&str, Word> = HashMap::new();
+// Time of each phase summed over all input files
+struct Profile {
+  phases: Vec<(&'static str, Duration)>,
+}
 
-  let re = Regex::new(r"(?msx)(?:(?P<word>[a-z']+?)[^a-z']+)").unwrap();
+impl Profile {
+  fn add(&mut self, phase: &'static str, start: Instant) {
+    let elapsed = start.elapsed();
 
-  for caps in re.captures_iter(lowercase_subs_text.as_str()) {
-    let text = caps.name("word").unwrap().as_str();
+    match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+      Some((_, duration)) => *duration += elapsed,
+      None => self.phases.push((phase, elapsed)),
+    }
+  }
 
-    sub_words.insert(text, Word {
-      text,
-      kind: WordKind::New,
-    });
+  fn print(&self) {
+    for (phase, duration) in self.phases.iter() {
+      progress!("{}: {} ms", phase, duration.as_millis());
+    }
   }
+}
 
-  sub_words
+struct InputFile {
+  input_filename: String,
+  output_filename: String,
+  encoding: &'static Encoding,
+  subs: Vec<Sub>,
 }
 
-fn translate_subs(subs: &mut Vec<Sub>, words: &HashMap<&str, Word>) {
-  let re_color = Regex::new("([a-zA-Z'])+").unwrap();
-  let re_newline = Regex::new("(\r?\n)").unwrap();
-  let re_clean_tags = Regex::new("(</?[ib]>)").unwrap();
-  let mut translated_chunks = String::new();
-  let mut current_chunk = String::new();
-  let mut current_chunk_size = 0;
-  const MAX_CHUNK_SIZE: usize = 4000;
+const SUBS_EXTENSIONS: [&str; 2] = ["srt", "vtt"];
 
-  for sub in subs.iter_mut() {
-    let mut need_translation = false;
+fn find_subs_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+  for entry in std::fs::read_dir(dir)? {
+    let path = entry?.path();
+
+    if path.is_dir() {
+      find_subs_files(&path, files)?;
+    } else if path.extension().is_some_and(|extension| SUBS_EXTENSIONS.iter().any(|subs_extension| extension.eq_ignore_ascii_case(subs_extension))) {
+      files.push(path);
+    }
+  }
 
-    sub.text = re_clean_tags.replace_all(sub.text.as_str(), "").into();
-    sub.text = re_newline.replace_all(sub.text.as_str(), " ").into();
+  Ok(())
+}
 
-    let colored_text = re_color.replace_all(sub.text.as_str(), |caps: &Captures| {
-      let captured_word = caps.get(0).unwrap().as_str();
+// Outputs of an earlier run are named like "movie.out.srt"
+fn is_output_file(path: &Path) -> bool {
+  path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("srt")) &&
+    path.file_stem().and_then(|stem| Path::new(stem).extension()).is_some_and(|extension| extension.eq_ignore_ascii_case("out"))
+}
 
-      if let Some(word) = words.get(captured_word.to_ascii_lowercase().as_str()) {
-        if let WordKind::Known = word.kind {} else {
-          need_translation = true;
+// Outputs of a directory input mirror its structure under the output directory, or sit next to the inputs
+// Empty lines and lines starting with '#' are skipped, files without an output path get the usual '.out.srt' one
+fn read_input_list(args: &Args, manifest_filename: &str) -> Vec<(String, String)> {
+  let manifest_text = load_text_file(manifest_filename, args.max_file_size).unwrap_or_else(|err| exit_with_error(err));
 
-          return format!("<font color=\"#FFFF80\">{}</font>", captured_word);
+  manifest_text.lines()
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(|line| {
+      let mut fields = line.splitn(2, '\t');
+      let input_filename = fields.next().unwrap().trim();
+      let output_filename = match fields.next().map(str::trim) {
+        Some(output_filename) => PathBuf::from(output_filename),
+        None => {
+          let mut output_file = match &args.output_dir {
+            Some(output_dir) => Path::new(output_dir).join(Path::new(input_filename).file_name().unwrap_or_default()),
+            None => PathBuf::from(input_filename),
+          };
+          output_file.set_extension("out.srt");
+          output_file
         }
-      }
+      };
 
-      String::from(captured_word)
-    }).into();
-
-    if need_translation {
-      sub.need_translation = true;
-      let text: String = re_newline.replace_all(sub.text.as_str(), "*").into();
-      let len = text.len();
-      current_chunk_size = current_chunk_size + len;
-
-      if current_chunk_size > MAX_CHUNK_SIZE {
-        //println!("Original chunk:\n {}\n", current_chunk);
-        current_chunk_size = len;
-        let translated_chunk = Google {}.translate(current_chunk, Langage::EN, Langage::RU).unwrap();
-        sleep(Duration::from_secs(1));
-        //println!("Translated chunk:\n {}\n", translated_chunk);
-        translated_chunks.push_str(translated_chunk.as_str());
-        translated_chunks.push_str("\r\n");
-        current_chunk = String::new();
-      }
+      (String::from(input_filename), output_filename.to_string_lossy().into_owned())
+    })
+    .collect()
+}
+
+fn collect_input_filenames(args: &Args) -> Vec<(String, String)> {
+  if let Some(manifest_filename) = &args.input_list {
+    return read_input_list(args, manifest_filename);
+  }
+
+  if !args.recursive {
+    return vec![(args.input_subs_filename.clone(), args.output_subs_filename.clone())];
+  }
+
+  let input_dir = Path::new(&args.input_subs_filename);
+  let mut files = Vec::new();
+  find_subs_files(input_dir, &mut files).unwrap_or_else(|err| exit_with_error(Error::Io(err)));
+  files.sort();
+
+  files.into_iter()
+    .filter(|file| !is_output_file(file))
+    .map(|file| {
+      let mut output_file = match &args.output_dir {
+        Some(output_dir) => Path::new(output_dir).join(file.strip_prefix(input_dir).unwrap()),
+        None => file.clone(),
+      };
+
+      output_file.set_extension("out.srt");
+      (file.to_string_lossy().into_owned(), output_file.to_string_lossy().into_owned())
+    })
+    .collect()
+}
+
+fn load_input_file(args: &Args, input_filename: String, output_filename: String, profile: &mut Profile) -> std::result::Result<InputFile, String> {
+  progress!("Read subs from: '{}'", &input_filename);
+  let phase_start = Instant::now();
+  let mut warnings = Vec::new();
+  let (subs_text, encoding) = if args.encodings.is_empty() {
+    load_subs_file(&input_filename, args.max_file_size, &mut warnings).map(|subs_text| (subs_text, encoding_rs::UTF_8))
+  } else {
+    load_subs_file_with_encodings(&input_filename, args.max_file_size, &args.encodings, &mut warnings).map(|(subs_text, encoding)| {
+      progress!("Decoded '{}' as {}", input_filename, encoding);
+      (subs_text, Encoding::for_label(encoding.as_bytes()).unwrap())
+    })
+  }.map_err(|err| format!("Failed to read '{}': {}", input_filename, err))?;
+  profile.add("File load", phase_start);
+
+  let format = args.input_format.or_else(|| detect_format(&subs_text)).unwrap_or(SubsFormat::Srt);
+  let subs_text = if format == SubsFormat::Srt {
+    subs_text
+  } else {
+    progress!("Read '{}' as {} subs", input_filename, format.name());
+    convert_to_srt(&subs_text, format)
+  };
+
+  if !args.force && is_translated_output(&subs_text, &args.translate_options) {
+    return Err(format!("'{}' looks already translated by this tool, use --force to process it anyway", input_filename));
+  }
 
-      current_chunk.push_str(text.as_str());
-      current_chunk.push_str("\r\n");
-      sub.text = colored_text
+  let phase_start = Instant::now();
+
+  // Fixes are reported but are no parsing warnings, so strict mode still accepts the repaired subs
+  let subs_text = if args.repair {
+    let mut fixes = Vec::new();
+    let repaired_text = repair_subs_text(&subs_text, &mut fixes);
+
+    for fix in fixes.iter() {
+      log_event("warning", &format!("repaired: {}", fix), Some(&input_filename), None);
     }
+
+    repaired_text
+  } else {
+    subs_text
+  };
+
+  let parse = if args.lenient { parse_subs_lenient } else { parse_subs };
+  let mut subs = parse(&subs_text).map_err(|err| format!("Failed to parse '{}': {}", input_filename, err))?;
+  check_subs(&subs, &mut warnings);
+  profile.add("Parse", phase_start);
+
+  for warning in warnings.iter() {
+    log_event("warning", warning, Some(&input_filename), None);
+  }
+
+  if args.strict_mode && !warnings.is_empty() {
+    return Err(format!("{} warning(s) found in '{}' in strict mode", warnings.len(), input_filename));
+  }
+
+  if args.sort_by_time {
+    sort_subs_by_time(&mut subs);
   }
 
-  if !current_chunk.is_empty() {
-    //println!("Original chunk:\n{}\n", current_chunk);
-    let translated_chunk = Google {}.translate(current_chunk, Langage::EN, Langage::RU).unwrap();
-    //println!("Translated chunk:\n {}\n", translated_chunk);
-    translated_chunks.push_str(translated_chunk.as_str());
-    translated_chunks.push_str("\r\n");
+  if args.renumber {
+    renumber_subs(&mut subs);
   }
 
-  translated_chunks = translated_chunks.replace("\\r\\n", "\r\n");
-  let mut translated_lines = translated_chunks.lines();
+  if let Some(min_gap) = args.min_gap {
+    let trimmed = enforce_min_gap(&mut subs, min_gap);
 
-  for sub in subs.iter_mut() {
-    if sub.need_translation {
-      let translated_text = translated_lines.next().unwrap().replace(" *", "\r\n");
-      sub.text.push_str("\r\n");
-      sub.text.push_str(translated_text.as_str());
+    if trimmed > 0 {
+      log_event("warning", &format!("{} subs end too close to the next one and are trimmed", trimmed), Some(&input_filename), None);
     }
   }
+
+  if args.decode_entities {
+    decode_subs_entities(&mut subs);
+  }
+
+  Ok(InputFile {
+    input_filename,
+    output_filename,
+    encoding,
+    subs,
+  })
+}
+
+fn translate_input_file(args: &Args, input_file: &mut InputFile, db_words: &HashMap<&str, Word>, metadata_fields: Option<&[(&str, String)]>, cache: &TranslationCache) -> (TranslationStats, String) {
+  let output_filename = &input_file.output_filename;
+  // Dialogues split by speaker have originals of several lines, the metadata sub lists them for reading the output back
+  let header = |subs: &[Sub]| match metadata_fields {
+    Some(fields) => {
+      let mut fields = fields.to_vec();
+      fields.extend(dialogue_field(subs).map(|dialogue| ("dialogue", dialogue)));
+      Sub::metadata(&fields).stringify(args.time_precision)
+    }
+    None => String::new(),
+  };
+  let output_encoding = args.output_encoding.unwrap_or(input_file.encoding);
+  let stringify = |sub: &Sub, time_precision: usize| {
+    if args.replace_flagged {
+      sub.stringify_translation(time_precision)
+    } else {
+      sub.stringify_with_divider(time_precision, args.divider.as_deref())
+    }
+  };
+
+  if let Some(output_dir) = Path::new(output_filename).parent() {
+    std::fs::create_dir_all(output_dir).expect("Failed to create output directory");
+  }
+
+  // The database still gets the words of all subs, only the output is narrowed down
+  if !args.filter_words.is_empty() {
+    let options = &args.translate_options;
+    input_file.subs.retain(|sub| sub.is_metadata() || has_any_word(&sub.text, &args.filter_words, &args.word_regex, options.source_language, options.case_locale));
+    progress!("{} subs have the filter words", input_file.subs.len());
+  }
+
+  let original_cues: Vec<String> = match args.patch_filename {
+    Some(_) => input_file.subs.iter().map(|sub| sub.stringify(args.time_precision)).collect(),
+    None => Vec::new(),
+  };
+
+  progress!("Translate subs");
+  let translator = TimeoutTranslator::new(args.engine.clone(), args.timeout);
+  let caching_translator = CachingTranslator::new(&translator, cache, args.translate_options.context.as_deref());
+  let stats = translate_subs_with_progress(&mut input_file.subs, db_words, &caching_translator, &args.translate_options, |subs| {
+    // An interrupted run still leaves a valid file with the subs translated so far
+    if args.incremental_output {
+      save_subs(output_filename, &header(subs), subs, args.time_precision, output_encoding, stringify);
+    }
+
+    if let Some(filename) = &args.cache_filename {
+      save_text_file(filename, &cache.stringify());
+    }
+  }).unwrap_or_else(|err| exit_with_error(err));
+  progress!("Translated {} chunks, {} of them suspicious", stats.chunks, stats.suspicious_chunks);
+
+  for explanation in stats.explanations.iter() {
+    progress!("{}", explanation);
+  }
+
+  if translator.timeouts() > 0 {
+    warning!("{} translation requests timed out", translator.timeouts());
+  }
+
+  if stats.reused_subs > 0 {
+    progress!("Reused {} translations of the previous output", stats.reused_subs);
+  }
+
+  if stats.already_target_subs > 0 {
+    progress!("Skipped {} subs already in the target language", stats.already_target_subs);
+  }
+
+  if stats.passthrough_groups > 0 {
+    warning!("{} translations are the same as their source text", stats.passthrough_groups);
+  }
+
+  if stats.skipped_chunks > 0 {
+    warning!("request limit reached, {} chunks left untranslated", stats.skipped_chunks);
+  }
+
+  if !stats.failed_chunks.is_empty() {
+    let failed_chunks: Vec<String> = stats.failed_chunks.iter().map(usize::to_string).collect();
+    warning!("{} chunks failed and are left untranslated: {}", failed_chunks.len(), failed_chunks.join(", "));
+  }
+
+  let cues: Vec<(u32, String, String)> = input_file.subs.iter().zip(original_cues)
+    .map(|(sub, original)| (sub.index, original, stringify(sub, args.time_precision)))
+    .collect();
+  let patch = build_patch(&input_file.input_filename, output_filename, &header(&input_file.subs), &cues);
+
+  if let Some(max_cue_chars) = args.max_cue_chars {
+    input_file.subs = split_long_subs(std::mem::take(&mut input_file.subs), max_cue_chars);
+  }
+
+  if let Some(max_cps) = args.max_cps {
+    let fast_subs: Vec<String> = find_fast_subs(&input_file.subs, max_cps).iter()
+      .map(|(index, cps)| format!("{} ({:.1})", index, cps))
+      .collect();
+
+    if fast_subs.is_empty() {
+      progress!("All subs are read at {} chars per second or slower", max_cps);
+    } else {
+      warning!("{} subs are faster than {} chars per second: {}", fast_subs.len(), max_cps, fast_subs.join(", "));
+    }
+  }
+
+  if let Some(marker) = &args.split_on {
+    for (i, part) in split_subs_on(&input_file.subs, marker).into_iter().enumerate() {
+      let mut part_path = PathBuf::from(output_filename);
+      part_path.set_extension(format!("part{}.srt", i + 1));
+      progress!("Write translated subs part {} to: '{}'", i + 1, part_path.display());
+      save_subs(&part_path, &header(part), part, args.time_precision, output_encoding, stringify);
+    }
+  } else {
+    progress!("Write translated subs to: '{}'", output_filename);
+    save_subs(output_filename, &header(&input_file.subs), &input_file.subs, args.time_precision, output_encoding, stringify);
+  }
+
+  if args.dual_output {
+    let mut original_subs_path = PathBuf::from(output_filename);
+    original_subs_path.set_extension("orig.srt");
+    progress!("Write original subs track to: '{}'", original_subs_path.display());
+    save_subs(&original_subs_path, &header(&input_file.subs), &input_file.subs, args.time_precision, output_encoding, Sub::stringify_original);
+
+    let mut translated_subs_path = PathBuf::from(output_filename);
+    translated_subs_path.set_extension("trans.srt");
+    progress!("Write translated subs track to: '{}'", translated_subs_path.display());
+    save_subs(&translated_subs_path, &header(&input_file.subs), &input_file.subs, args.time_precision, output_encoding, Sub::stringify_translation);
+  }
+
+  (stats, patch)
+}
+
+fn exit_with_error(err: Error) -> ! {
+  error!("{}", err);
+  std::process::exit(1);
 }
 
 fn main() {
   let start = Utc::now();
-  let args = get_args();
+  let mut args = get_args();
 
-  if args.analyze_mode {
-    println!("Analysis mode");
+  if args.output_subs_filename == "-" {
+    PROGRESS_TO_STDERR.store(true, Ordering::Relaxed);
   }
 
-  println!("Read subs from: '{}'", &args.input_subs_filename);
-  let subs_text = load_text_file(&args.input_subs_filename).unwrap();
-  let mut subs = parse_subs(&subs_text);
+  if args.restore_db {
+    restore_database(&args).unwrap_or_else(|err| exit_with_error(Error::Io(err)));
+    return;
+  }
+
+  if args.reorder_mode {
+    reorder_subs_file(&args).unwrap_or_else(|err| exit_with_error(err));
+    return;
+  }
 
-  println!("Read words database from: '{}'", &args.database_filename);
-  let db_words_text = load_text_file(&args.database_filename).unwrap_or_default();
-  let mut db_words = parse_db_words(&db_words_text);
-  println!("{} words is in the database", db_words.len());
+  if args.analyze_mode {
+    progress!("Analysis mode");
+  }
 
-  let lowercase_subs_text = subs_text.to_ascii_lowercase();
-  let sub_words = parse_sub_words(&lowercase_subs_text);
-  println!("Found {} unique words in subs", sub_words.len());
-  let words_db_len = db_words.len();
+  let mut input_files = Vec::new();
+  let mut failed_files = 0;
+  let mut profile = Profile {
+    phases: Vec::new(),
+  };
 
-  for (k, v) in sub_words.into_iter() {
-    db_words.entry(k).or_insert(v);
+  for (input_filename, output_filename) in collect_input_filenames(&args) {
+    match load_input_file(&args, input_filename.clone(), output_filename, &mut profile) {
+      Ok(input_file) => input_files.push(input_file),
+      Err(message) if args.is_batch() => {
+        log_event("error", &message, Some(&input_filename), None);
+        failed_files += 1;
+      }
+      Err(message) => {
+        log_event("error", &message, Some(&input_filename), None);
+        std::process::exit(1);
+      }
+    }
   }
 
-  if db_words.len() > words_db_len {
-    println!("Add {} new words to the database", db_words.len() - words_db_len);
+  let phase_start = Instant::now();
+  let db_words_text = if args.no_db {
+    progress!("Translate without the words database");
+    String::new()
   } else {
-    println!("No new words found");
+    progress!("Read words database from: '{}'", &args.database_filename);
+    load_db_text(&args.database_filename, args.max_file_size)
+  };
+  let overlay_words_text;
+
+  // With an overlay the base database stays untouched, overlay words win and get all new words
+  let (mut db_words, mut target_words, target_filename, target_words_text) = match &args.overlay_filename {
+    Some(overlay_filename) => {
+      let db_words = parse_db_words(&db_words_text);
+      progress!("{} words is in the database", db_words.len());
+
+      progress!("Read overlay words database from: '{}'", overlay_filename);
+      overlay_words_text = load_db_text(overlay_filename, args.max_file_size);
+      let overlay_words = parse_db_words(&overlay_words_text);
+      progress!("{} words is in the overlay database", overlay_words.len());
+
+      (db_words, overlay_words, overlay_filename, overlay_words_text.as_str())
+    }
+    None if args.no_db => (HashMap::new(), HashMap::new(), &args.database_filename, ""),
+    None => {
+      let db_words = parse_db_words(&db_words_text);
+      progress!("{} words is in the database", db_words.len());
+
+      (HashMap::new(), db_words, &args.database_filename, db_words_text.as_str())
+    }
+  };
+
+  profile.add("Database", phase_start);
+
+  let phase_start = Instant::now();
+  let subs_text = input_files.iter()
+    .flat_map(|input_file| input_file.subs.iter())
+    .fold(String::new(), |acc, sub| acc + &sub.text + "\n");
+  let subs_text = strip_ass_overrides(&subs_text);
+  let subs_text = if args.translate_options.translate_urls { subs_text } else { strip_non_linguistic(&subs_text) };
+  let lowercase_subs_text = if args.translate_options.skip_acronyms {
+    fold_case(&strip_acronyms(&subs_text), args.translate_options.source_language, args.translate_options.case_locale)
+  } else {
+    fold_case(&subs_text, args.translate_options.source_language, args.translate_options.case_locale)
+  };
+  let sub_words = parse_sub_words(&lowercase_subs_text, &args.word_regex);
+  progress!("Found {} unique words in subs", sub_words.len());
+  profile.add("Word scan", phase_start);
+
+  if args.count_only {
+    print_word_counts(&lowercase_subs_text, &args.word_regex, &db_words, &target_words, &args.translate_options.stopwords);
+    return;
   }
 
-  let mut sorted_words: Vec<&Word> = db_words.iter().map(|(_, word)| word).collect();
-  sorted_words.sort_by(|&left, &right| left.text.cmp(&right.text));
+  let phase_start = Instant::now();
+  let sub_word_texts: HashSet<&str> = sub_words.keys().cloned().collect();
 
-  let mut words_db_text = sorted_words.iter().fold(String::new(), |s, &w| {
-    match w.kind {
-      WordKind::New => s + "?:" + w.text + "\r\n",
-      _ => s,
+  // Without the database every sub is translated and nothing is written
+  if !args.no_db {
+    let mut new_words = Vec::new();
+
+    for (k, v) in sub_words.into_iter() {
+      if !db_words.contains_key(k) && !target_words.contains_key(k) && !args.translate_options.stopwords.contains(k) {
+        new_words.push(k);
+        target_words.insert(k, v);
+      }
     }
-  });
 
-  words_db_text = sorted_words.iter().fold(words_db_text, |s, &w| {
-    match w.kind {
-      WordKind::Unknown => s + "u:" + w.text + "\r\n",
-      _ => s,
+    new_words.sort();
+
+    if args.db_readonly {
+      progress!("Found {} new words, the database is left untouched", new_words.len());
+    } else if !new_words.is_empty() {
+      progress!("Add {} new words to the database", new_words.len());
+    } else {
+      progress!("No new words found");
     }
-  });
 
-  words_db_text = sorted_words.iter().fold(words_db_text, |s, &w| {
-    match w.kind {
-      WordKind::Known => s + "k:" + w.text + "\r\n",
-      _ => s,
+    // New words are still translated in read-only mode, they just are not written
+    if !args.db_readonly {
+      if args.backup_db && Path::new(target_filename).exists() {
+        std::fs::copy(target_filename, backup_filename(target_filename))
+          .expect("Failed to backup the database file");
+      }
+
+      let dropped_words = match args.db_max_words {
+        Some(max_words) => excess_new_words(&target_words, max_words, &count_sub_words(&lowercase_subs_text, &args.word_regex)),
+        None => HashSet::new(),
+      };
+
+      if !dropped_words.is_empty() {
+        warning!("database is over {} words, {} new words are not written", args.db_max_words.unwrap(), dropped_words.len());
+      }
+
+      if args.append_db {
+        // Existing lines are kept byte for byte so the database diffs only show the new words
+        let mut appended_text = String::new();
+
+        if !args.db_kinds.contains(&WordKind::New) {
+          new_words.clear();
+        }
+
+        new_words.retain(|word| !dropped_words.contains(word));
+
+        if !new_words.is_empty() && !target_words_text.is_empty() && !target_words_text.ends_with('\n') {
+          appended_text.push_str("\r\n");
+        }
+
+        for word in new_words.iter() {
+          appended_text = appended_text + "?:" + word + "\r\n";
+        }
+
+        std::fs::OpenOptions::new().create(true).append(true).open(target_filename)
+          .expect("Failed to open database file for writing")
+          .write_all(appended_text.as_bytes())
+          .expect("Failed to write to the database file");
+      } else {
+        File::create(target_filename)
+          .expect("Failed to open database file for writing")
+          .write_all(stringify_db_words(target_words.values().filter(|word| args.db_kinds.contains(&word.kind) && !dropped_words.contains(word.text)), &args.db_order).as_bytes())
+          .expect("Failed to write to the database file");
+      }
     }
-  });
+  }
+
+  profile.add("Database", phase_start);
+
+  db_words.extend(target_words);
+  let mut sorted_words: Vec<&Word> = db_words.values().collect();
+  sorted_words.sort_by(|&left, &right| left.text.cmp(right.text));
+
+  if let Some(filename) = &args.markdown_filename {
+    progress!("Write {} words as Markdown to: '{}'", sorted_words.len(), filename);
+    save_text_file(filename, &build_markdown(&sorted_words, &args.db_order));
+  }
 
-  File::create(&args.database_filename)
-    .expect("Failed to open database file for writing")
-    .write(words_db_text.as_bytes())
-    .expect("Failed to write to the database file");
+  if let Some(deck_filename) = &args.deck_filename {
+    let (studied_words, deck_words): (Vec<&Word>, Vec<&Word>) = sorted_words.iter()
+      .filter(|word| sub_word_texts.contains(word.text))
+      .filter(|word| !matches!(word.kind, WordKind::Known))
+      .partition(|word| args.studied_words.contains(word.text));
+
+    if !studied_words.is_empty() {
+      progress!("Leave {} studied words out of the study deck", studied_words.len());
+    }
+
+    progress!("Write {} words to the study deck: '{}'", deck_words.len(), deck_filename);
+    let tags = if args.pos_tag { Some(tag_words(&lowercase_subs_text)) } else { None };
+    let examples = if args.deck_examples {
+      let sub_texts = input_files.iter().flat_map(|input_file| input_file.subs.iter()).map(|sub| sub.text.as_str());
+      Some(find_word_examples(sub_texts, &args.word_regex, args.translate_options.source_language, args.translate_options.case_locale))
+    } else {
+      None
+    };
+    save_text_file(deck_filename, &build_deck(&deck_words, args.deck_delimiter, tags.as_ref(), examples.as_ref()));
+  }
 
   if !args.analyze_mode {
-    println!("Translate subs");
-    translate_subs(&mut subs, &db_words);
-    let translated_subs_text = subs.iter().fold(String::new(), |acc, sub| acc + &sub.stringify());
+    let metadata_fields = if args.write_metadata {
+      let mut fields = vec![
+        ("source", args.translate_options.source_language.to_string()),
+        ("target", args.translate_options.target_language.to_string()),
+        ("engine", String::from(args.engine.name())),
+        ("created", Utc::now().to_rfc3339()),
+        ("order", String::from(ORDER_ORIGINAL_FIRST)),
+      ];
+
+      if let Some(divider) = &args.divider {
+        fields.push(("divider", divider.clone()));
+      }
+
+      Some(fields)
+    } else {
+      None
+    };
+
+    let mut change_report = String::new();
+    let mut traces = Vec::new();
+    let mut patches = String::new();
+    let mut total_subs = 0;
+    let mut total_translated_subs = 0;
+
+    let cache_text = match &args.cache_filename {
+      Some(filename) if Path::new(filename).exists() => load_text_file(filename, args.max_file_size).unwrap_or_else(|err| exit_with_error(err)),
+      _ => String::new(),
+    };
+    let cache = TranslationCache::parse(&cache_text, args.refresh_cache);
+
+    // Finished input files are recorded one per line and the file is removed once all of them are.
+    // A plain single file run has nothing to resume, so it leaves no progress file next to the input.
+    let progress_filename = if args.is_batch() || args.resume || args.incremental_output {
+      progress_filename(args.input_list.as_ref().unwrap_or(&args.input_subs_filename))
+    } else {
+      None
+    };
+
+    if let Some(filename) = &progress_filename {
+      if args.resume {
+        let progress_text = load_db_text(filename, args.max_file_size);
+        let done_files: HashSet<&str> = progress_text.lines().collect();
+        let files_count = input_files.len();
+        input_files.retain(|input_file| !done_files.contains(input_file.input_filename.as_str()));
+        progress!("Resume: skip {} files translated in an earlier run", files_count - input_files.len());
+
+        if args.cache_filename.is_none() {
+          warning!("only whole files are resumed, chunks translated before the interruption are requested again, use --cache to reuse them");
+        }
+      } else {
+        save_text_file(filename, "");
+      }
+    }
+
+    let mut all_files_done = true;
+
+    for input_file in input_files.iter_mut() {
+      let phase_start = Instant::now();
+      let (stats, patch) = translate_input_file(&args, input_file, &db_words, metadata_fields.as_deref(), &cache);
+      profile.add("Translation", phase_start);
+
+      if stats.skipped_chunks > 0 || !stats.failed_chunks.is_empty() {
+        all_files_done = false;
+      } else if let Some(filename) = &progress_filename {
+        std::fs::OpenOptions::new().create(true).append(true).open(filename)
+          .expect("Failed to open progress file for writing")
+          .write_all(format!("{}\n", input_file.input_filename).as_bytes())
+          .expect("Failed to write to the progress file");
+      }
+
+      // The request limit is shared by all input files of the run
+      if let Some(max_requests) = args.translate_options.max_requests.as_mut() {
+        *max_requests -= stats.chunks;
+      }
+
+      let translated_subs = input_file.subs.iter().filter(|sub| sub.is_translated()).count();
+      total_subs += input_file.subs.len();
+      total_translated_subs += translated_subs;
+
+      if args.is_batch() {
+        progress!("'{}': {} of {} subs translated", input_file.input_filename, translated_subs, input_file.subs.len());
+        change_report.push_str(&format!("File: {}\n", input_file.input_filename));
+      }
+
+      change_report.push_str(&build_change_report(&input_file.subs));
+      traces.extend(stats.traces);
+      patches.push_str(&patch);
+    }
+
+    if let Some(filename) = progress_filename.as_ref().filter(|_| all_files_done) {
+      std::fs::remove_file(filename).expect("Failed to remove the progress file");
+    }
+
+    if let Some(filename) = &args.cache_filename {
+      progress!("Write translation cache to: '{}', {} lines reused", filename, cache.hits());
+      save_text_file(filename, &cache.stringify());
+    }
+
+    if let Some(filename) = &args.trace_filename {
+      progress!("Write translation trace to: '{}'", filename);
+      save_text_file(filename, &stringify_traces(&traces, args.engine.name()));
+    }
+
+    if let Some(filename) = &args.parallel_filename {
+      progress!("Write {} parallel text lines to: '{}'", traces.len(), filename);
+      save_text_file(filename, &stringify_parallel_text(&traces, args.parallel_layout));
+    }
+
+    if let Some(filename) = &args.patch_filename {
+      progress!("Write cue changes patch to: '{}'", filename);
+      save_text_file(filename, &patches);
+    }
+
+    if let Some(filename) = &args.preview_filename {
+      let files: Vec<(&str, &[Sub])> = input_files.iter()
+        .map(|input_file| (input_file.input_filename.as_str(), input_file.subs.as_slice()))
+        .collect();
+
+      progress!("Write preview to: '{}'", filename);
+      save_text_file(filename, &build_preview_html(&files, &args.translate_options));
+    }
+
+    if let Some(filename) = &args.passthrough_filename {
+      let passthrough_text = input_files.iter()
+        .flat_map(|input_file| input_file.subs.iter())
+        .filter(|sub| !sub.is_translated())
+        .fold(String::new(), |acc, sub| acc + &sub.stringify_original(args.time_precision));
 
-    println!("Write translated subs to: '{}'", &args.output_subs_filename);
+      let encoding = args.output_encoding.unwrap_or_else(|| input_files.first().map_or(encoding_rs::UTF_8, |input_file| input_file.encoding));
+      progress!("Write untranslated subs to: '{}'", filename);
+      save_encoded_text_file(filename, &passthrough_text, encoding);
+    }
 
-    let mut output_file = File::create(&args.output_subs_filename)
-      .expect("Failed to open file for writing");
+    match &args.change_report_filename {
+      Some(filename) => {
+        progress!("Write change report to: '{}'", filename);
+        save_text_file(filename, &change_report);
+      }
+      None => progress!("{}", change_report.trim_end()),
+    }
 
-    output_file.write(translated_subs_text.as_bytes())
-      .expect("Failed to write to the file");
+    if args.is_batch() {
+      progress!("Processed {} files, {} failed: {} of {} subs translated",
+               input_files.len() + failed_files, failed_files, total_translated_subs, total_subs);
+    }
   }
 
+  if args.profile {
+    profile.print();
+  }
 
   let dur = Utc::now().signed_duration_since(start).num_milliseconds();
-  println!("Succeed in {} ms", dur);
+  progress!("Succeed in {} ms", dur);
 }
@@ -0,0 +1,94 @@
+// Cues are given as their stringified text before and after translation, unchanged cues are left out of the hunks.
+// Hunk ranges count lines of the whole stringified files, the output starts with its header.
+pub fn build_patch(original_filename: &str, output_filename: &str, output_header: &str, cues: &[(u32, String, String)]) -> String {
+  let mut patch = String::new();
+  let mut original_line = 1;
+  let mut output_line = 1 + output_header.lines().count();
+
+  for (index, original, output) in cues.iter() {
+    let original_lines: Vec<&str> = original.lines().collect();
+    let output_lines: Vec<&str> = output.lines().collect();
+
+    if original != output {
+      patch.push_str(&format!("@@ -{} +{} @@ cue {}\n",
+                              hunk_range(original_line, original_lines.len()),
+                              hunk_range(output_line, output_lines.len()),
+                              index));
+
+      for line in diff_lines(original_lines.clone(), output_lines.clone()) {
+        patch.push_str(&line);
+        patch.push('\n');
+      }
+    }
+
+    original_line += original_lines.len();
+    output_line += output_lines.len();
+  }
+
+  if patch.is_empty() {
+    return patch;
+  }
+
+  format!("--- {}\n+++ {}\n{}", original_filename, output_filename, patch)
+}
+
+// An empty range starts at the line before it
+fn hunk_range(start: usize, count: usize) -> String {
+  match count {
+    0 => format!("{},0", start - 1),
+    _ => format!("{},{}", start, count),
+  }
+}
+
+// Lines of the longest common subsequence are context, the rest are removed or added
+fn diff_lines(old: Vec<&str>, new: Vec<&str>) -> Vec<String> {
+  let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+
+  for i in (0..old.len()).rev() {
+    for j in (0..new.len()).rev() {
+      lengths[i][j] = if old[i] == new[j] {
+        lengths[i + 1][j + 1] + 1
+      } else {
+        lengths[i + 1][j].max(lengths[i][j + 1])
+      };
+    }
+  }
+
+  let mut lines = Vec::new();
+  let (mut i, mut j) = (0, 0);
+
+  while i < old.len() || j < new.len() {
+    if i < old.len() && j < new.len() && old[i] == new[j] {
+      lines.push(format!(" {}", old[i]));
+      i += 1;
+      j += 1;
+    } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+      lines.push(format!("-{}", old[i]));
+      i += 1;
+    } else {
+      lines.push(format!("+{}", new[j]));
+      j += 1;
+    }
+  }
+
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hunks_have_line_ranges_of_the_files() {
+    let cues = vec![
+      (1, String::from("1\n00:00:01,000 --> 00:00:02,000\nHello\n\n"), String::from("1\n00:00:01,000 --> 00:00:02,000\nHello\n\n")),
+      (2, String::from("2\n00:00:03,000 --> 00:00:04,000\nBye\n\n"), String::from("2\n00:00:03,000 --> 00:00:04,000\nBye\r\nПока\n\n")),
+    ];
+    let patch = build_patch("in.srt", "out.srt", "0\n00:00:00,000 --> 00:00:00,000\nmeta\n\n", &cues);
+
+    assert_eq!(patch, "\
+--- in.srt\n+++ out.srt\n\
+@@ -5,4 +9,5 @@ cue 2\n \
+2\n 00:00:03,000 --> 00:00:04,000\n Bye\n+Пока\n \n");
+  }
+}
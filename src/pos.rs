@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+const DETERMINERS: [&str; 12] = ["a", "an", "the", "this", "that", "these", "those", "my", "your", "his", "her", "our"];
+const PRONOUNS: [&str; 14] = ["i", "you", "he", "she", "it", "we", "they", "me", "him", "us", "them", "who", "what", "myself"];
+const PREPOSITIONS: [&str; 17] = ["to", "in", "on", "at", "of", "for", "with", "from", "by", "about", "into", "over", "under", "after", "before", "through", "without"];
+const CONJUNCTIONS: [&str; 8] = ["and", "but", "or", "nor", "so", "because", "if", "while"];
+// Words usually followed by a verb
+const VERB_STARTERS: [&str; 16] = ["to", "i", "you", "he", "she", "we", "they", "will", "would", "can", "could", "should", "must", "might", "don't", "didn't"];
+
+// Closed word classes are looked up, the rest is guessed from the suffix and the word before it
+fn tag_word(word: &str, previous_word: Option<&str>) -> &'static str {
+  if DETERMINERS.contains(&word) {
+    return "det";
+  }
+
+  if PRONOUNS.contains(&word) {
+    return "pron";
+  }
+
+  if PREPOSITIONS.contains(&word) {
+    return "prep";
+  }
+
+  if CONJUNCTIONS.contains(&word) {
+    return "conj";
+  }
+
+  if word.ends_with("ly") {
+    return "adv";
+  }
+
+  if previous_word.is_some_and(|previous_word| VERB_STARTERS.contains(&previous_word)) || word.ends_with("ing") || word.ends_with("ed") {
+    return "verb";
+  }
+
+  if ["ous", "ful", "able", "ible", "ive", "less", "ish"].iter().any(|suffix| word.ends_with(suffix)) {
+    return "adj";
+  }
+
+  "noun"
+}
+
+// Each word gets the tag of its first occurrence in the text
+pub fn tag_words(lowercase_subs_text: &str) -> HashMap<&str, &'static str> {
+  let re_word = Regex::new("[a-z']+").unwrap();
+  let mut tags = HashMap::new();
+  let mut previous_word = None;
+
+  for word in re_word.find_iter(lowercase_subs_text).map(|word| word.as_str()) {
+    tags.entry(word).or_insert_with(|| tag_word(word, previous_word));
+    previous_word = Some(word);
+  }
+
+  tags
+}
@@ -0,0 +1,52 @@
+use crate::subs::Sub;
+use crate::translate::{highlight_tags, TranslateOptions};
+
+fn escape_html(text: &str) -> String {
+  text.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace("\r\n", "<br>")
+    .replace('\n', "<br>")
+}
+
+// Highlight markup of any kind is turned into a span styled by the page CSS
+fn render_text(text: &str, options: &TranslateOptions) -> String {
+  let (open_tag, close_tag) = highlight_tags(options);
+
+  if open_tag.is_empty() {
+    return escape_html(text);
+  }
+
+  escape_html(text)
+    .replace(&escape_html(&open_tag), "<span class=\"highlight\">")
+    .replace(&escape_html(close_tag), "</span>")
+}
+
+pub fn build_preview_html(files: &[(&str, &[Sub])], options: &TranslateOptions) -> String {
+  let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Translation preview</title>\n\
+    <style>\n\
+    body {{ font-family: sans-serif; background: #202020; color: #E0E0E0; }}\n\
+    table {{ border-collapse: collapse; width: 100%; }}\n\
+    th, td {{ border: 1px solid #404040; padding: 4px 8px; text-align: left; vertical-align: top; }}\n\
+    .highlight {{ color: {}; }}\n\
+    </style>\n</head>\n<body>\n", options.highlight_color);
+
+  for (file_name, subs) in files.iter() {
+    html.push_str(&format!("<h2>{}</h2>\n<table>\n<tr><th>#</th><th>Timing</th><th>Original</th><th>Translation</th></tr>\n", escape_html(file_name)));
+
+    for sub in subs.iter() {
+      html.push_str(&format!("<tr><td>{}</td><td>{} --&gt; {}</td><td>{}</td><td>{}</td></tr>\n",
+                             sub.index,
+                             sub.start_time.format("%H:%M:%S,%3f"),
+                             sub.end_time.format("%H:%M:%S,%3f"),
+                             render_text(&sub.text, options),
+                             escape_html(sub.translation.as_deref().unwrap_or(""))));
+    }
+
+    html.push_str("</table>\n");
+  }
+
+  html.push_str("</body>\n</html>\n");
+  html
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use translate_core::ETranslate;
+
+use crate::cache::{CachingTranslator, TranslationCache};
+use crate::engine::Engine;
+use crate::error::Result;
+use crate::subs::{parse_subs, DEFAULT_TIME_PRECISION};
+use crate::translate::{translate_subs, TranslateOptions};
+use crate::words::{fold_case, language_word_regex, parse_db_words, parse_sub_words, strip_ass_overrides, strip_non_linguistic};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+  // The translation goes under the original
+  Bilingual,
+  // The translation replaces the original
+  TranslationOnly,
+}
+
+pub struct RunOptions {
+  translate_options: TranslateOptions,
+  engine: Engine,
+  layout: Layout,
+  divider: Option<String>,
+  cache_path: Option<PathBuf>,
+  database: Option<String>,
+}
+
+impl RunOptions {
+  pub fn new(translate_options: TranslateOptions) -> RunOptions {
+    RunOptions {
+      translate_options,
+      engine: Engine::Google,
+      layout: Layout::Bilingual,
+      divider: None,
+      cache_path: None,
+      database: None,
+    }
+  }
+
+  pub fn engine(mut self, engine: Engine) -> RunOptions {
+    self.engine = engine;
+    self
+  }
+
+  pub fn layout(mut self, layout: Layout) -> RunOptions {
+    self.layout = layout;
+    self
+  }
+
+  pub fn divider(mut self, divider: &str) -> RunOptions {
+    self.divider = Some(String::from(divider));
+    self
+  }
+
+  // The cache file is created if missing and updated after the run
+  pub fn cache_path<P>(mut self, path: P) -> RunOptions where P: Into<PathBuf> {
+    self.cache_path = Some(path.into());
+    self
+  }
+
+  // Database text in the format of the database file, without one every word is new
+  pub fn database(mut self, database: &str) -> RunOptions {
+    self.database = Some(String::from(database));
+    self
+  }
+}
+
+// Translates SRT text the way the command line does, but without touching the database,
+// the usage example test of tests/pipeline.rs shows a whole call
+pub fn run(options: &RunOptions, input: &str) -> Result<String> {
+  run_with_translator(&options.engine, options, input)
+}
+
+// Same as run with a translator of its own, like a mock one in tests
+pub fn run_with_translator<T>(translator: &T, options: &RunOptions, input: &str) -> Result<String> where T: ETranslate {
+  let translate_options = &options.translate_options;
+  let mut subs = parse_subs(input)?;
+
+  let subs_text = subs.iter().fold(String::new(), |acc, sub| acc + &sub.text + "\n");
+  let subs_text = strip_ass_overrides(&subs_text);
+  let subs_text = if translate_options.translate_urls { subs_text } else { strip_non_linguistic(&subs_text) };
+  let lowercase_subs_text = fold_case(&subs_text, translate_options.source_language, translate_options.case_locale);
+  let word_regex = language_word_regex(translate_options.source_language, translate_options.case_locale);
+
+  // Words missing from the database are new, so they get translated
+  let mut words = parse_sub_words(&lowercase_subs_text, &word_regex);
+  words.extend(options.database.as_deref().map_or_else(HashMap::new, parse_db_words));
+
+  let cache_text = match &options.cache_path {
+    Some(path) if path.exists() => std::fs::read_to_string(path)?,
+    _ => String::new(),
+  };
+  let cache = TranslationCache::parse(&cache_text, false);
+
+  translate_subs(&mut subs, &words, &CachingTranslator::new(translator, &cache, translate_options.context.as_deref()), translate_options)?;
+
+  if let Some(path) = &options.cache_path {
+    std::fs::write(path, cache.stringify())?;
+  }
+
+  Ok(subs.iter()
+    .map(|sub| match options.layout {
+      Layout::Bilingual => sub.stringify_with_divider(DEFAULT_TIME_PRECISION, options.divider.as_deref()),
+      Layout::TranslationOnly => sub.stringify_translation(DEFAULT_TIME_PRECISION),
+    })
+    .collect())
+}
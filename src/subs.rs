@@ -0,0 +1,694 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{Duration, NaiveTime, Timelike};
+use regex::{Captures, Regex};
+
+use crate::error::{Error, Result};
+
+pub const METADATA_MARKER: &str = "[srt-translator]";
+
+pub struct Sub {
+  pub index: u32,
+  pub start_time: NaiveTime,
+  pub end_time: NaiveTime,
+  pub settings: String,
+  pub position_tags: String,
+  pub text: String,
+  pub translation: Option<String>,
+  pub need_translation: bool,
+}
+
+impl fmt::Display for Sub {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}\n{} --> {}\n{}\n",
+           self.index,
+           self.start_time.format("%H:%M:%S,%3f"),
+           self.end_time.format("%H:%M:%S,%3f"),
+           self.text)
+  }
+}
+
+impl Sub {
+  // SRT has no comment syntax, so metadata goes into a zero length sub placed before the first one
+  pub fn metadata(fields: &[(&str, String)]) -> Sub {
+    let text = fields.iter().fold(String::from(METADATA_MARKER), |acc, (key, value)| acc + " " + key + "=" + value);
+
+    Sub {
+      index: 0,
+      start_time: NaiveTime::from_hms(0, 0, 0),
+      end_time: NaiveTime::from_hms(0, 0, 0),
+      settings: String::new(),
+      position_tags: String::new(),
+      text,
+      translation: None,
+      need_translation: false,
+    }
+  }
+
+  pub fn is_metadata(&self) -> bool {
+    self.index == 0 && self.text.starts_with(METADATA_MARKER)
+  }
+
+  // Flagged subs of skipped or failed chunks need a translation but got none
+  pub fn is_translated(&self) -> bool {
+    self.translation.is_some()
+  }
+
+  pub fn metadata_field(&self, key: &str) -> Option<&str> {
+    self.text.split_whitespace()
+      .filter_map(|field| field.split_once('='))
+      .find(|(field_key, _)| *field_key == key)
+      .map(|(_, value)| value)
+  }
+
+  pub fn set_metadata_field(&mut self, key: &str, value: &str) {
+    let mut fields: Vec<String> = self.text.split_whitespace()
+      .filter(|field| field.split_once('=').is_none_or(|(field_key, _)| field_key != key))
+      .map(String::from)
+      .collect();
+
+    fields.push(format!("{}={}", key, value));
+    self.text = fields.join(" ");
+  }
+
+  pub fn stringify(&self, time_precision: usize) -> String {
+    self.stringify_with_divider(time_precision, None)
+  }
+
+  // The divider goes on its own line between the original and the translation
+  pub fn stringify_with_divider(&self, time_precision: usize, divider: Option<&str>) -> String {
+    match (&self.translation, divider) {
+      (Some(translation), Some(divider)) => self.stringify_text(&format!("{}\r\n{}\r\n{}", self.text, divider, translation), time_precision),
+      (Some(translation), None) => self.stringify_text(&format!("{}\r\n{}", self.text, translation), time_precision),
+      (None, _) => self.stringify_text(&self.text, time_precision),
+    }
+  }
+
+  pub fn stringify_original(&self, time_precision: usize) -> String {
+    self.stringify_text(&self.text, time_precision)
+  }
+
+  pub fn stringify_translation(&self, time_precision: usize) -> String {
+    self.stringify_text(self.translation.as_ref().unwrap_or(&self.text), time_precision)
+  }
+
+  fn stringify_text(&self, text: &str, time_precision: usize) -> String {
+    let text = cue_text(text);
+
+    if text.is_empty() && self.position_tags.is_empty() {
+      return format!("{}\n{} --> {}{}\n\n",
+                     self.index,
+                     format_time(&self.start_time, time_precision),
+                     format_time(&self.end_time, time_precision),
+                     self.settings);
+    }
+
+    format!("{}\n{} --> {}{}\n{}{}\n\n",
+            self.index,
+            format_time(&self.start_time, time_precision),
+            format_time(&self.end_time, time_precision),
+            self.settings,
+            self.position_tags,
+            text)
+  }
+}
+
+// A blank line ends a cue, so blank lines in the text are dropped and each cue is followed by exactly one
+fn cue_text(text: &str) -> String {
+  let text = text.trim_end_matches(['\r', '\n']);
+
+  if text.lines().any(|line| line.trim().is_empty()) {
+    text.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>().join("\r\n")
+  } else {
+    String::from(text)
+  }
+}
+
+pub const DEFAULT_TIME_PRECISION: usize = 3;
+
+// Fractions are rounded to the given number of digits, except at the very end of the day where rounding up would wrap
+pub fn format_time(time: &NaiveTime, precision: usize) -> String {
+  let divisor = 10u32.pow(3 - precision.min(3) as u32);
+  let ms = time.num_seconds_from_midnight() * 1000 + time.nanosecond() / 1_000_000;
+  let rounded_ms = (ms + divisor / 2) / divisor * divisor;
+  let ms = if rounded_ms < 86_400_000 { rounded_ms } else { ms / divisor * divisor };
+  let seconds_text = format!("{:02}:{:02}:{:02}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60);
+
+  match precision {
+    0 => seconds_text,
+    _ => format!("{},{:0width$}", seconds_text, ms % 1000 / divisor, width = precision.min(3)),
+  }
+}
+
+fn parse_time(block: usize, time: &str) -> Result<NaiveTime> {
+  NaiveTime::parse_from_str(time, "%H:%M:%S,%3f").map_err(|err| Error::Parse {
+    block,
+    reason: format!("invalid time '{}': {}", time, err),
+  })
+}
+
+pub fn parse_subs(text: &str) -> Result<Vec<Sub>> {
+  let mut subs = Vec::new();
+
+  // The last sub of files written by some tools lacks the closing blank line
+  let text = format!("{}\n\n", text);
+
+  // Text is optional so a sub with empty text is kept as empty instead of swallowing the next sub
+  let re = Regex::new(r"(?msx)
+        (?P<index>\d+)\r?\n
+        (?P<start_time>\d{2}:\d{2}:\d{2},\d{3})\s-->\s(?P<end_time>\d{2}:\d{2}:\d{2},\d{3})(?P<settings>[^\r\n]*)\r?\n
+        (?:(?P<text>[^\r\n].*?)\r?\n)?\r?\n
+    ").unwrap();
+
+  let re_position_tags = position_tags_regex();
+
+  for (block, caps) in re.captures_iter(&text).enumerate().map(|(i, caps)| (i + 1, caps)) {
+    let text = caps.name("text").map_or("", |text| text.as_str());
+    subs.push(build_sub(block, &caps, text, &re_position_tags)?);
+  }
+
+  Ok(subs)
+}
+
+// Subs are cut at each index and timing line pair, so subs missing the blank line between them are still apart
+pub fn parse_subs_lenient(text: &str) -> Result<Vec<Sub>> {
+  let re_header = Regex::new(r"(?m)^(?P<index>\d+)\r?\n(?P<start_time>\d{2}:\d{2}:\d{2},\d{3})\s-->\s(?P<end_time>\d{2}:\d{2}:\d{2},\d{3})(?P<settings>[^\r\n]*)\r?\n").unwrap();
+  let re_position_tags = position_tags_regex();
+  let headers: Vec<Captures> = re_header.captures_iter(text).collect();
+  let mut subs = Vec::new();
+
+  for (i, caps) in headers.iter().enumerate() {
+    let text_start = caps.get(0).unwrap().end();
+    let text_end = headers.get(i + 1).map_or(text.len(), |next_caps| next_caps.get(0).unwrap().start());
+    subs.push(build_sub(i + 1, caps, text[text_start..text_end].trim_end(), &re_position_tags)?);
+  }
+
+  Ok(subs)
+}
+
+struct RepairedCue {
+  index: Option<u32>,
+  timing: String,
+  text: Vec<String>,
+}
+
+// Blocks are the lines between blank lines. A timing line without an index gets the one after the previous sub,
+// an index alone in its block is paired with a timing line starting the next one, and text without a timing line
+// was split off its sub by a blank line, so it is joined to the previous sub. Text needing no fix is returned as is.
+pub fn repair_subs_text(text: &str, fixes: &mut Vec<String>) -> String {
+  let re_index = Regex::new(r"^\d+$").unwrap();
+  let re_timing = Regex::new(r"^\d{2}:\d{2}:\d{2},\d{3}\s-->\s\d{2}:\d{2}:\d{2},\d{3}").unwrap();
+  let lines: Vec<(usize, &str)> = text.trim_start_matches('\u{feff}').lines().map(str::trim_end).enumerate().map(|(i, line)| (i + 1, line)).collect();
+  let fix_count = fixes.len();
+  let mut cues: Vec<RepairedCue> = Vec::new();
+  let mut pending_index: Option<(usize, u32)> = None;
+
+  for block in lines.split(|(_, line)| line.trim().is_empty()).filter(|block| !block.is_empty()) {
+    let timing_positions: Vec<usize> = block.iter().enumerate()
+      .filter(|(_, (_, line))| re_timing.is_match(line))
+      .map(|(position, _)| position)
+      .collect();
+    let is_index = |position: usize| re_index.is_match(block[position].1.trim());
+
+    if let Some((line_number, _)) = pending_index.filter(|_| timing_positions.first() != Some(&0)) {
+      fixes.push(format!("Line {}: index without a timing line is dropped", line_number));
+      pending_index = None;
+    }
+
+    if timing_positions.is_empty() {
+      if block.len() == 1 && is_index(0) {
+        pending_index = block[0].1.trim().parse().ok().map(|index| (block[0].0, index));
+        continue;
+      }
+
+      let text_start = if block.len() > 1 && is_index(0) { 1 } else { 0 };
+
+      match cues.last_mut() {
+        Some(cue) => {
+          fixes.push(format!("Line {}: text without a timing line is joined to the previous sub", block[0].0));
+          cue.text.extend(block[text_start..].iter().map(|(_, line)| String::from(*line)));
+        }
+        None => fixes.push(format!("Line {}: text without a timing line before the first sub is dropped", block[0].0)),
+      }
+
+      continue;
+    }
+
+    // Lines before the first timing line other than its index belong to the previous sub
+    let first_header = timing_positions[0] - usize::from(timing_positions[0] > 0 && is_index(timing_positions[0] - 1));
+
+    if first_header > 0 {
+      if let Some(cue) = cues.last_mut() {
+        fixes.push(format!("Line {}: text without a timing line is joined to the previous sub", block[0].0));
+        cue.text.extend(block[..first_header].iter().map(|(_, line)| String::from(*line)));
+      }
+    }
+
+    for (i, &position) in timing_positions.iter().enumerate() {
+      let has_index = position > 0 && is_index(position - 1);
+      let text_end = timing_positions.get(i + 1).map_or(block.len(), |&next| next - usize::from(is_index(next - 1) && next - 1 > position));
+
+      if i > 0 {
+        fixes.push(format!("Line {}: sub without a blank line before it is split off", block[position].0));
+      }
+
+      let index = if has_index {
+        block[position - 1].1.trim().parse().ok()
+      } else if let Some((line_number, index)) = pending_index.take() {
+        fixes.push(format!("Line {}: index of line {} is paired with the timing line", block[position].0, line_number));
+        Some(index)
+      } else {
+        fixes.push(format!("Line {}: timing line without an index gets one", block[position].0));
+        None
+      };
+
+      cues.push(RepairedCue {
+        index,
+        timing: String::from(block[position].1),
+        text: block[position + 1..text_end].iter().map(|(_, line)| String::from(*line)).collect(),
+      });
+    }
+  }
+
+  if let Some((line_number, _)) = pending_index {
+    fixes.push(format!("Line {}: index without a timing line is dropped", line_number));
+  }
+
+  if fixes.len() == fix_count {
+    return String::from(text);
+  }
+
+  let mut prev_index = 0;
+
+  cues.iter().fold(String::new(), |acc, cue| {
+    let index = cue.index.unwrap_or(prev_index + 1);
+    prev_index = index;
+    cue.text.iter().fold(acc + &format!("{}\n{}\n", index, cue.timing), |acc, line| acc + line + "\n") + "\n"
+  })
+}
+
+// Positioning overrides like {\an8} or {\pos(10,20)} are not words, they are kept aside and put back verbatim
+fn position_tags_regex() -> Regex {
+  Regex::new(r"\{\\(?:an?\d+|pos\([^)]*\)|move\([^)]*\)|org\([^)]*\))\}").unwrap()
+}
+
+fn build_sub(block: usize, caps: &Captures, text: &str, re_position_tags: &Regex) -> Result<Sub> {
+  let index = caps.name("index").unwrap().as_str();
+  let index: u32 = index.parse().map_err(|err| Error::Parse {
+    block,
+    reason: format!("invalid index '{}': {}", index, err),
+  })?;
+  let start_time = parse_time(block, caps.name("start_time").unwrap().as_str())?;
+  let end_time = parse_time(block, caps.name("end_time").unwrap().as_str())?;
+  let settings = caps.name("settings").unwrap().as_str().to_owned();
+  let position_tags = re_position_tags.find_iter(text).map(|tag| tag.as_str()).collect();
+  let text = re_position_tags.replace_all(text, "").into_owned();
+
+  Ok(Sub {
+    index,
+    start_time,
+    end_time,
+    settings,
+    position_tags,
+    text,
+    translation: None,
+    need_translation: false,
+  })
+}
+
+// &lt; and &gt; stay encoded, decoded they would pass for markup tags in the output
+pub fn decode_entities(text: &str) -> String {
+  let re_entity = Regex::new(r"&(?:#(?P<dec>\d+)|#[xX](?P<hex>[0-9a-fA-F]+)|(?P<name>amp|quot|apos|nbsp));").unwrap();
+
+  re_entity.replace_all(text, |caps: &Captures| {
+    let c = if let Some(dec) = caps.name("dec") {
+      dec.as_str().parse().ok().and_then(char::from_u32)
+    } else if let Some(hex) = caps.name("hex") {
+      u32::from_str_radix(hex.as_str(), 16).ok().and_then(char::from_u32)
+    } else {
+      match caps.name("name").unwrap().as_str() {
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => Some(' '),
+      }
+    };
+
+    match c {
+      Some('<') => String::from("&lt;"),
+      Some('>') => String::from("&gt;"),
+      Some(c) => c.to_string(),
+      None => String::from(caps.get(0).unwrap().as_str()),
+    }
+  }).into_owned()
+}
+
+pub fn decode_subs_entities(subs: &mut [Sub]) {
+  for sub in subs.iter_mut() {
+    sub.text = decode_entities(&sub.text);
+  }
+}
+
+pub const ORDER_ORIGINAL_FIRST: &str = "original-first";
+pub const ORDER_TRANSLATION_FIRST: &str = "translation-first";
+
+// Originals of more than one line, like dialogues split by speaker, as "index:lines" pairs for the metadata sub
+pub fn dialogue_field(subs: &[Sub]) -> Option<String> {
+  let counts: Vec<String> = subs.iter()
+    .filter(|sub| !sub.is_metadata() && sub.text.lines().count() > 1)
+    .map(|sub| format!("{}:{}", sub.index, sub.text.lines().count()))
+    .collect();
+
+  if counts.is_empty() { None } else { Some(counts.join(",")) }
+}
+
+// Line counts of the originals the metadata sub lists, the other originals are a single line
+pub fn original_line_counts(metadata: &Sub) -> HashMap<u32, usize> {
+  metadata.metadata_field("dialogue").map_or_else(HashMap::new, |field| {
+    field.split(',')
+      .filter_map(|pair| pair.split_once(':'))
+      .filter_map(|(index, lines)| Some((index.parse().ok()?, lines.parse().ok()?)))
+      .collect()
+  })
+}
+
+// Combined output keeps the original text of a sub on a single line unless the metadata sub tells otherwise,
+// so the original is either the first or the last lines depending on the order recorded in the metadata sub
+pub fn reorder_translations(subs: &mut [Sub]) -> Result<()> {
+  let metadata = subs.iter_mut().find(|sub| sub.is_metadata()).ok_or(Error::Parse {
+    block: 1,
+    reason: String::from("no metadata sub found, the file was not produced by srt-translator"),
+  })?;
+
+  let original_first = metadata.metadata_field("order") != Some(ORDER_TRANSLATION_FIRST);
+  let divider = metadata.metadata_field("divider").map(String::from);
+  let original_lines = original_line_counts(metadata);
+
+  metadata.set_metadata_field("order", if original_first { ORDER_TRANSLATION_FIRST } else { ORDER_ORIGINAL_FIRST });
+
+  for sub in subs.iter_mut().filter(|sub| !sub.is_metadata()) {
+    // The divider stays between the original and the translation
+    let lines: Vec<&str> = sub.text.lines().filter(|&line| Some(line) != divider.as_deref()).collect();
+
+    let original_len = original_lines.get(&sub.index).cloned().unwrap_or(1);
+
+    if lines.len() <= original_len {
+      continue;
+    }
+
+    let (head, tail) = if original_first { lines.split_at(original_len) } else { lines.split_at(lines.len() - original_len) };
+    let divider_lines: Vec<&str> = divider.as_deref().into_iter().collect();
+    sub.text = tail.iter().chain(divider_lines.iter()).chain(head.iter()).cloned().collect::<Vec<_>>().join("\r\n");
+  }
+
+  Ok(())
+}
+
+// Stable sort keeps subs with equal timing in their original order
+pub fn sort_subs_by_time(subs: &mut [Sub]) {
+  subs.sort_by(|left, right| left.start_time.cmp(&right.start_time).then(left.end_time.cmp(&right.end_time)));
+}
+
+// Each sub ends at least min_gap before the next one by time starts, but never before it starts itself.
+// Returns the number of subs trimmed.
+pub fn enforce_min_gap(subs: &mut [Sub], min_gap: Duration) -> usize {
+  let mut order: Vec<usize> = (0..subs.len()).collect();
+  order.sort_by_key(|&i| subs[i].start_time);
+  let mut trimmed = 0;
+
+  for pair in order.windows(2) {
+    let next_start = subs[pair[1]].start_time;
+    let sub = &mut subs[pair[0]];
+
+    if next_start.signed_duration_since(sub.end_time) >= min_gap {
+      continue;
+    }
+
+    sub.end_time = if next_start.signed_duration_since(sub.start_time) > min_gap { next_start - min_gap } else { sub.start_time };
+    trimmed += 1;
+  }
+
+  trimmed
+}
+
+pub fn renumber_subs(subs: &mut [Sub]) {
+  for (i, sub) in subs.iter_mut().enumerate() {
+    sub.index = i as u32 + 1;
+  }
+}
+
+// Each sub whose text without markup matches the marker starts a new part, subs before the first marker are a part of their own
+pub fn split_subs_on<'a>(subs: &'a [Sub], marker: &Regex) -> Vec<&'a [Sub]> {
+  let re_tags = Regex::new("<[^>]*>").unwrap();
+  let mut parts = Vec::new();
+  let mut part_start = 0;
+
+  for (i, sub) in subs.iter().enumerate().skip(1) {
+    if marker.is_match(&re_tags.replace_all(&sub.text, "")) {
+      parts.push(&subs[part_start..i]);
+      part_start = i;
+    }
+  }
+
+  if part_start < subs.len() {
+    parts.push(&subs[part_start..]);
+  }
+
+  parts
+}
+
+pub const DEFAULT_MAX_CPS: f64 = 20.0;
+
+// Visible chars of the text and the translation over the duration, subs faster than max_cps are returned with their speed
+pub fn find_fast_subs(subs: &[Sub], max_cps: f64) -> Vec<(u32, f64)> {
+  subs.iter()
+    .filter(|sub| !sub.is_metadata())
+    .filter_map(|sub| {
+      let chars = visible_len(&sub.text.replace(['\r', '\n'], "")) + sub.translation.as_deref().map_or(0, |translation| visible_len(&translation.replace(['\r', '\n'], "")));
+      let seconds = sub.end_time.signed_duration_since(sub.start_time).num_milliseconds() as f64 / 1000.0;
+      let cps = if seconds > 0.0 { chars as f64 / seconds } else { f64::INFINITY };
+
+      if chars > 0 && cps > max_cps { Some((sub.index, cps)) } else { None }
+    })
+    .collect()
+}
+
+fn visible_len(text: &str) -> usize {
+  Regex::new("<[^>]*>").unwrap().replace_all(text, "").chars().count()
+}
+
+// Splits at the space outside of markup tags closest to the middle of the visible text
+fn split_text(text: &str) -> Option<(String, String)> {
+  let half_len = visible_len(text) / 2;
+  let mut in_tag = false;
+  let mut visible_chars = 0;
+  let mut best_split: Option<(usize, usize)> = None;
+
+  for (pos, c) in text.char_indices() {
+    match c {
+      '<' => in_tag = true,
+      '>' => in_tag = false,
+      _ if in_tag => continue,
+      c if c.is_whitespace() => {
+        let distance = half_len.abs_diff(visible_chars);
+
+        if best_split.is_none_or(|(_, best_distance)| distance < best_distance) {
+          best_split = Some((pos, distance));
+        }
+
+        visible_chars += 1;
+      }
+      _ => visible_chars += 1,
+    }
+  }
+
+  best_split.map(|(pos, _)| (text[..pos].trim_end().to_owned(), text[pos..].trim_start().to_owned()))
+}
+
+// A sub with more visible characters than max_chars is split into two, the time range is shared
+// in proportion to the text of each half. Subs are renumbered afterwards.
+pub fn split_long_subs(subs: Vec<Sub>, max_chars: usize) -> Vec<Sub> {
+  let mut split_subs = Vec::new();
+
+  for sub in subs.into_iter() {
+    let total_len = visible_len(&sub.text) + sub.translation.as_deref().map_or(0, visible_len);
+
+    if sub.is_metadata() || total_len <= max_chars {
+      split_subs.push(sub);
+      continue;
+    }
+
+    let text_parts = split_text(&sub.text);
+    let translation_parts = sub.translation.as_deref().map(split_text);
+
+    let ((first_text, second_text), translation_parts) = match (text_parts, translation_parts) {
+      (Some(text_parts), None) => (text_parts, None),
+      (Some(text_parts), Some(Some(translation_parts))) => (text_parts, Some(translation_parts)),
+      _ => {
+        split_subs.push(sub);
+        continue;
+      }
+    };
+
+    let (first_translation, second_translation) = match translation_parts {
+      Some((first, second)) => (Some(first), Some(second)),
+      None => (None, None),
+    };
+
+    let first_len = visible_len(&first_text) + first_translation.as_deref().map_or(0, visible_len);
+    let duration = sub.end_time.signed_duration_since(sub.start_time).num_milliseconds();
+    let split_time = sub.start_time + Duration::milliseconds(duration * first_len as i64 / total_len as i64);
+
+    split_subs.push(Sub {
+      index: sub.index,
+      start_time: sub.start_time,
+      end_time: split_time,
+      settings: sub.settings.clone(),
+      position_tags: sub.position_tags.clone(),
+      text: first_text,
+      translation: first_translation,
+      need_translation: sub.need_translation,
+    });
+
+    split_subs.push(Sub {
+      start_time: split_time,
+      text: second_text,
+      translation: second_translation,
+      ..sub
+    });
+  }
+
+  renumber_subs(&mut split_subs);
+  split_subs
+}
+
+pub fn check_subs(subs: &[Sub], warnings: &mut Vec<String>) {
+  if subs.is_empty() {
+    warnings.push(String::from("No subs found"));
+  }
+
+  let mut prev_index = 0;
+
+  for sub in subs.iter() {
+    if sub.index != prev_index + 1 {
+      warnings.push(format!("Sub {} is out of sequence, expected index {}", sub.index, prev_index + 1));
+    }
+
+    if sub.end_time < sub.start_time {
+      warnings.push(format!("Sub {} ends before it starts", sub.index));
+    }
+
+    prev_index = sub.index;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn empty_text_cue_does_not_swallow_the_next_one() {
+    let subs = parse_subs("1\r\n00:00:01,000 --> 00:00:02,000\r\n\r\n2\r\n00:00:03,000 --> 00:00:04,000\r\nHello\r\n\r\n").unwrap();
+
+    assert_eq!(subs.len(), 2);
+    assert_eq!((subs[0].index, subs[0].text.as_str()), (1, ""));
+    assert_eq!((subs[1].index, subs[1].text.as_str()), (2, "Hello"));
+  }
+
+  #[test]
+  fn position_override_is_kept_out_of_the_text() {
+    let mut subs = parse_subs("1\r\n00:00:01,000 --> 00:00:02,000\r\n{\\an8}Hello there\r\n\r\n").unwrap();
+
+    assert_eq!(subs[0].position_tags, "{\\an8}");
+    assert_eq!(subs[0].text, "Hello there");
+
+    subs[0].translation = Some(String::from("Привет"));
+    assert_eq!(subs[0].stringify(DEFAULT_TIME_PRECISION), "1\n00:00:01,000 --> 00:00:02,000\n{\\an8}Hello there\r\nПривет\n\n");
+  }
+
+  #[test]
+  fn entities_are_decoded() {
+    assert_eq!(decode_entities("Tom &amp; Jerry"), "Tom & Jerry");
+    assert_eq!(decode_entities("caf&#233; &#xE9;"), "café é");
+    assert_eq!(decode_entities("&#60;i&#62; &unknown;"), "&lt;i&gt; &unknown;");
+  }
+
+  #[test]
+  fn cues_without_a_blank_line_between_them_are_apart() {
+    let subs = parse_subs_lenient("1\r\n00:00:01,000 --> 00:00:02,000\r\nHello\r\n2\r\n00:00:03,000 --> 00:00:04,000\r\nThere\r\n").unwrap();
+    let texts: Vec<(u32, &str)> = subs.iter().map(|sub| (sub.index, sub.text.as_str())).collect();
+
+    assert_eq!(texts, vec![(1, "Hello"), (2, "There")]);
+  }
+
+  #[test]
+  fn each_cue_is_followed_by_exactly_one_blank_line() {
+    assert_eq!(cue_text("Hello\r\n\r\nThere\r\n\r\n"), "Hello\r\nThere");
+    assert_eq!(cue_text("Hello\n \nThere"), "Hello\r\nThere");
+    assert_eq!(cue_text("Hello\r\nThere"), "Hello\r\nThere");
+
+    let mut subs = parse_subs("1\r\n00:00:01,000 --> 00:00:02,000\r\nHello\r\n\r\n").unwrap();
+    subs[0].translation = Some(String::from("\r\nПривет\r\n\r\n"));
+    let text = subs[0].stringify(DEFAULT_TIME_PRECISION);
+
+    assert_eq!(text, "1\n00:00:01,000 --> 00:00:02,000\nHello\r\nПривет\n\n");
+    assert_eq!(parse_subs(&(text.clone() + &text)).unwrap().len(), 2);
+  }
+
+  #[test]
+  fn block_without_an_index_gets_one() {
+    let mut fixes = Vec::new();
+    let text = repair_subs_text("1\n00:00:01,000 --> 00:00:02,000\nHello\n\n00:00:03,000 --> 00:00:04,000\nThere\n", &mut fixes);
+    let subs = parse_subs(&text).unwrap();
+    let texts: Vec<(u32, &str)> = subs.iter().map(|sub| (sub.index, sub.text.as_str())).collect();
+
+    assert_eq!(texts, vec![(1, "Hello"), (2, "There")]);
+    assert_eq!(fixes, vec!["Line 5: timing line without an index gets one"]);
+  }
+
+  #[test]
+  fn block_without_a_timing_line_is_joined_to_the_previous_sub() {
+    let mut fixes = Vec::new();
+    let text = repair_subs_text("1\n00:00:01,000 --> 00:00:02,000\nHello\n\n2\nthere\n\n3\n00:00:03,000 --> 00:00:04,000\nBye\n", &mut fixes);
+    let subs = parse_subs(&text).unwrap();
+    let texts: Vec<(u32, &str)> = subs.iter().map(|sub| (sub.index, sub.text.as_str())).collect();
+
+    assert_eq!(texts, vec![(1, "Hello\nthere"), (3, "Bye")]);
+    assert_eq!(fixes, vec!["Line 5: text without a timing line is joined to the previous sub"]);
+  }
+
+  #[test]
+  fn well_formed_text_is_not_changed() {
+    let mut fixes = Vec::new();
+    let text = "1\r\n00:00:01,000 --> 00:00:02,000\r\nHello\r\n\r\n";
+
+    assert_eq!(repair_subs_text(text, &mut fixes), text);
+    assert!(fixes.is_empty());
+  }
+
+  #[test]
+  fn dialogue_originals_of_several_lines_are_reordered_whole() {
+    let mut subs = parse_subs("\
+0\n00:00:00,000 --> 00:00:00,000\n[srt-translator] order=original-first dialogue=1:2\n\n\
+1\n00:00:01,000 --> 00:00:02,000\n- Hi there.\n- Bye now.\n- Привет.\n- Пока.\n\n\
+2\n00:00:03,000 --> 00:00:04,000\nHello.\nЗдравствуйте.\n\n").unwrap();
+    reorder_translations(&mut subs).unwrap();
+
+    assert_eq!(subs[0].metadata_field("order"), Some(ORDER_TRANSLATION_FIRST));
+    assert_eq!(subs[1].text, "- Привет.\r\n- Пока.\r\n- Hi there.\r\n- Bye now.");
+    assert_eq!(subs[2].text, "Здравствуйте.\r\nHello.");
+
+    reorder_translations(&mut subs).unwrap();
+    assert_eq!(subs[1].text, "- Hi there.\r\n- Bye now.\r\n- Привет.\r\n- Пока.");
+  }
+
+  #[test]
+  fn only_originals_of_several_lines_are_listed() {
+    let mut subs = parse_subs("1\n00:00:01,000 --> 00:00:02,000\n- Hi.\n- Bye.\n\n2\n00:00:03,000 --> 00:00:04,000\nHello.\n\n").unwrap();
+    subs[1].translation = Some(String::from("Здравствуйте."));
+
+    assert_eq!(dialogue_field(&subs).as_deref(), Some("1:2"));
+    assert_eq!(original_line_counts(&Sub::metadata(&[("dialogue", String::from("1:2,7:3"))])), HashMap::from([(1, 2), (7, 3)]));
+  }
+}
@@ -0,0 +1,65 @@
+use std::cell::Cell;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use translate_core::*;
+
+use crate::log::warning;
+
+const TIMEOUT_RETRIES: u32 = 2;
+
+// A hung request cannot be cancelled, it is left running on its own thread while the request is retried
+pub struct TimeoutTranslator<T> {
+  translator: Arc<T>,
+  timeout: Option<Duration>,
+  timeouts: Cell<usize>,
+}
+
+impl<T> TimeoutTranslator<T> where T: ETranslate + Send + Sync + 'static {
+  pub fn new(translator: T, timeout: Option<Duration>) -> TimeoutTranslator<T> {
+    TimeoutTranslator {
+      translator: Arc::new(translator),
+      timeout,
+      timeouts: Cell::new(0),
+    }
+  }
+
+  pub fn timeouts(&self) -> usize {
+    self.timeouts.get()
+  }
+}
+
+impl<T> ETranslate for TimeoutTranslator<T> where T: ETranslate + Send + Sync + 'static {
+  fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+    let timeout = match self.timeout {
+      Some(timeout) => timeout,
+      None => return self.translator.translate(text, source, target),
+    };
+
+    for attempt in 0..=TIMEOUT_RETRIES {
+      let (sender, receiver) = mpsc::channel();
+      let translator = Arc::clone(&self.translator);
+      let text = text.clone();
+
+      thread::spawn(move || {
+        let _ = sender.send(translator.translate(text, source, target));
+      });
+
+      match receiver.recv_timeout(timeout) {
+        Ok(translation) => return translation,
+        Err(_) => {
+          self.timeouts.set(self.timeouts.get() + 1);
+          warning(&format!("translation request timed out after {} s", timeout.as_secs()));
+
+          if attempt < TIMEOUT_RETRIES {
+            thread::sleep(Duration::from_secs(1 << attempt));
+          }
+        }
+      }
+    }
+
+    None
+  }
+}
@@ -0,0 +1,77 @@
+use std::str::FromStr;
+
+use regex::Regex;
+
+pub struct CueTrace {
+  pub index: u32,
+  pub original: String,
+  pub translation: String,
+  pub trigger_words: Vec<String>,
+}
+
+pub fn stringify_traces(traces: &[CueTrace], engine: &str) -> String {
+  let cues: Vec<String> = traces.iter().map(|trace| {
+    let trigger_words: Vec<String> = trace.trigger_words.iter().map(|word| escape_string(word)).collect();
+
+    format!("    {{\"index\": {}, \"original\": {}, \"translation\": {}, \"trigger_words\": [{}], \"engine\": {}}}",
+            trace.index,
+            escape_string(&trace.original),
+            escape_string(&trace.translation),
+            trigger_words.join(", "),
+            escape_string(engine))
+  }).collect();
+
+  format!("{{\n  \"cues\": [\n{}\n  ]\n}}\n", cues.join(",\n"))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ParallelLayout {
+  // Tab separated source and translation
+  Tsv,
+  // Translations start in the same column, for reading side by side
+  Aligned,
+}
+
+impl FromStr for ParallelLayout {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<ParallelLayout, Self::Err> {
+    match s {
+      "tsv" => Ok(ParallelLayout::Tsv),
+      "aligned" => Ok(ParallelLayout::Aligned),
+      _ => Err(format!("Unknown parallel text layout '{}'", s))
+    }
+  }
+}
+
+// One pair per cue on a single line, markup, direction marks and line breaks and tabs of the texts are dropped
+pub fn stringify_parallel_text(traces: &[CueTrace], layout: ParallelLayout) -> String {
+  let re_markup = Regex::new(r"<[^>]*>|\{[^}]*\}|\u{200F}").unwrap();
+  let plain_text = |text: &str| re_markup.replace_all(text, "").split_whitespace().collect::<Vec<_>>().join(" ");
+  let pairs: Vec<(String, String)> = traces.iter().map(|trace| (plain_text(&trace.original), plain_text(&trace.translation))).collect();
+  let width = pairs.iter().map(|(original, _)| original.chars().count()).max().unwrap_or(0);
+
+  pairs.iter().fold(String::new(), |acc, (original, translation)| match layout {
+    ParallelLayout::Tsv => acc + original + "\t" + translation + "\r\n",
+    ParallelLayout::Aligned => acc + &format!("{:width$} | {}\r\n", original, translation, width = width),
+  })
+}
+
+pub(crate) fn escape_string(text: &str) -> String {
+  let mut escaped = String::from("\"");
+
+  for c in text.chars() {
+    match c {
+      '"' => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      '\r' => escaped.push_str("\\r"),
+      '\t' => escaped.push_str("\\t"),
+      c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+      c => escaped.push(c),
+    }
+  }
+
+  escaped.push('"');
+  escaped
+}
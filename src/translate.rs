@@ -0,0 +1,1280 @@
+use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use regex::Captures;
+use regex::Regex;
+use translate_core::*;
+
+use crate::detect::detect_language;
+use crate::error::{Error, Result};
+use crate::log::{log_event, warning};
+use crate::subs::{original_line_counts, Sub, METADATA_MARKER, ORDER_TRANSLATION_FIRST};
+use crate::trace::CueTrace;
+use crate::words::{ass_override_regex, fold_case, CaseLocale, is_acronym, is_non_linguistic, language_letters_regex, non_linguistic_spans, Word, WordKind};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HighlightMarkup {
+  Font,
+  Span,
+  Bold,
+  Underline,
+  None,
+}
+
+impl FromStr for HighlightMarkup {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<HighlightMarkup, Self::Err> {
+    match s {
+      "font" => Ok(HighlightMarkup::Font),
+      "span" => Ok(HighlightMarkup::Span),
+      "bold" => Ok(HighlightMarkup::Bold),
+      "underline" => Ok(HighlightMarkup::Underline),
+      "none" => Ok(HighlightMarkup::None),
+      _ => Err(format!("Unknown highlight markup '{}'", s))
+    }
+  }
+}
+
+// translate_core has no way to list its languages, keep this in sync with its Langage enum
+pub const SUPPORTED_LANGUAGES: [&str; 7] = ["en", "fr", "de", "nl", "es", "it", "ru"];
+
+const NAMED_COLORS: [(&str, &str); 8] = [
+  ("white", "#FFFFFF"),
+  ("yellow", "#FFFF80"),
+  ("orange", "#FFA500"),
+  ("red", "#FF0000"),
+  ("green", "#00FF00"),
+  ("cyan", "#00FFFF"),
+  ("blue", "#0000FF"),
+  ("magenta", "#FF00FF"),
+];
+
+pub fn parse_color(color: &str) -> std::result::Result<String, String> {
+  let re_hex_color = Regex::new("^#[0-9A-Fa-f]{6}$").unwrap();
+
+  if re_hex_color.is_match(color) {
+    return Ok(color.to_owned());
+  }
+
+  match NAMED_COLORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(color)) {
+    Some((_, hex)) => Ok(String::from(*hex)),
+    None => {
+      let names: Vec<&str> = NAMED_COLORS.iter().map(|(name, _)| *name).collect();
+      Err(format!("Invalid color '{}', expected #RRGGBB or one of: {}", color, names.join(", ")))
+    }
+  }
+}
+
+pub const RUBY_GLOSS_FORMAT: &str = "<ruby>{word}<rt>{gloss}</rt></ruby>";
+
+// Glosses go inline with the word, so the format needs both placeholders
+pub fn parse_gloss_format(format: &str) -> std::result::Result<String, String> {
+  match (format.contains("{word}"), format.contains("{gloss}")) {
+    (true, true) => Ok(String::from(format)),
+    _ => Err(format!("Gloss format '{}' needs both {{word}} and {{gloss}}", format)),
+  }
+}
+
+pub fn parse_language(code: &str) -> std::result::Result<Langage, String> {
+  string_to_langage(code.to_owned())
+    .ok_or_else(|| format!("Unsupported language '{}', supported languages are: {}", code, SUPPORTED_LANGUAGES.join(", ")))
+}
+
+pub struct TranslateOptions {
+  pub source_language: Langage,
+  pub target_language: Langage,
+  pub sentence_mode: bool,
+  pub highlight_color: String,
+  pub highlight_markup: HighlightMarkup,
+  pub translate_all: bool,
+  pub confidence_threshold: Option<f32>,
+  pub min_unknown: usize,
+  pub normalize_text: bool,
+  pub chunk_delay: Duration,
+  pub max_requests: Option<usize>,
+  pub max_chunk_size: usize,
+  pub skip_failed_chunks: bool,
+  pub merge_short: Option<usize>,
+  pub text_filter: Option<Regex>,
+  pub index_from: Option<u32>,
+  pub index_to: Option<u32>,
+  pub stopwords: HashSet<String>,
+  pub skip_acronyms: bool,
+  pub word_notes: bool,
+  pub gloss_format: Option<String>,
+  pub dialogue_dashes: bool,
+  pub translate_urls: bool,
+  pub context: Option<String>,
+  pub translate_karaoke: bool,
+  pub rtl_translation: bool,
+  pub strip_ass_tags: bool,
+  pub detect_skip: bool,
+  pub explain: bool,
+  pub case_locale: CaseLocale,
+  pub previous_cues: HashMap<(u32, NaiveTime, NaiveTime), PreviousCue>,
+}
+
+// A sub of an earlier output with its original text without markup
+pub struct PreviousCue {
+  pub text: String,
+  pub translation: Option<String>,
+}
+
+fn strip_markup(text: &str) -> String {
+  Regex::new(r"<[^>]*>|\{[^}]*\}").unwrap().replace_all(text, "").into_owned()
+}
+
+// Per word timing like {\k20} of ASS or <00:01:02.500> of VTT, one tag could be a stray one
+const MIN_KARAOKE_TAGS: usize = 2;
+
+fn karaoke_regex() -> Regex {
+  Regex::new(r"\{\\[kK][fo]?\d+\}|<(?:\d{2}:)?\d{2}:\d{2}\.\d{3}>").unwrap()
+}
+
+fn is_karaoke(text: &str, re_karaoke: &Regex) -> bool {
+  re_karaoke.find_iter(text).count() >= MIN_KARAOKE_TAGS
+}
+
+// Blocks opening the text and the ones closing it wrap the whole translation as well, like {\i1}...{\i0}
+fn ass_override_edges(text: &str, re_leading: &Regex, re_trailing: &Regex) -> (String, String) {
+  let leading = re_leading.find(text).map_or("", |m| m.as_str());
+
+  if leading.is_empty() {
+    return (String::new(), String::new());
+  }
+
+  let rest = &text[leading.len()..];
+  let trailing = re_trailing.find(rest).map_or("", |m| m.as_str());
+
+  (String::from(leading), String::from(trailing))
+}
+
+// Subs are keyed by index and timing, the original is the line, or the lines of a dialogue, the metadata order tells.
+// Lines of the original are joined like the text of a sub about to be translated.
+pub fn parse_previous_cues(previous_subs: &[Sub]) -> Result<HashMap<(u32, NaiveTime, NaiveTime), PreviousCue>> {
+  let metadata = previous_subs.iter().find(|sub| sub.is_metadata()).ok_or(Error::Parse {
+    block: 1,
+    reason: String::from("no metadata sub found, the file was not produced by srt-translator"),
+  })?;
+  let original_first = metadata.metadata_field("order") != Some(ORDER_TRANSLATION_FIRST);
+  let divider = metadata.metadata_field("divider");
+  let original_lines = original_line_counts(metadata);
+
+  Ok(previous_subs.iter()
+    .filter(|sub| !sub.is_metadata())
+    .map(|sub| {
+      let lines: Vec<&str> = sub.text.lines().filter(|&line| Some(line) != divider).collect();
+      let original_len = original_lines.get(&sub.index).cloned().unwrap_or(1);
+      let (original, translation) = match lines.len() {
+        len if len <= original_len => (lines.join(" "), None),
+        len if original_first => (lines[..original_len].join(" "), Some(lines[original_len..len].join("\r\n"))),
+        len => (lines[len - original_len..].join(" "), Some(lines[..len - original_len].join("\r\n"))),
+      };
+
+      ((sub.index, sub.start_time, sub.end_time), PreviousCue {
+        text: strip_markup(&original),
+        translation,
+      })
+    })
+    .collect())
+}
+
+// Sentence mode heuristic: consecutive subs are joined into one sentence until a sub
+// ends with '.', '?' or '!' (optionally followed by closing quotes or brackets).
+// The whole sentence is translated as one line and its words are then handed back
+// to the original subs in proportion to each sub's duration, so longer subs get a
+// larger share of the translation. The last sub of a sentence takes the remainder.
+fn group_sentences(subs: &[Sub]) -> Vec<Vec<usize>> {
+  let re_sentence_end = Regex::new(r#"[.?!]["')\]]*$"#).unwrap();
+  let mut sentences = Vec::new();
+  let mut current_sentence = Vec::new();
+
+  for (i, sub) in subs.iter().enumerate() {
+    if sub.text.is_empty() {
+      continue;
+    }
+
+    current_sentence.push(i);
+
+    if re_sentence_end.is_match(sub.text.trim_end()) {
+      sentences.push(current_sentence);
+      current_sentence = Vec::new();
+    }
+  }
+
+  if !current_sentence.is_empty() {
+    sentences.push(current_sentence);
+  }
+
+  sentences
+}
+
+fn distribute_translation(translated_text: &str, durations: &[i64]) -> Vec<String> {
+  let translated_words: Vec<&str> = translated_text.split_whitespace().collect();
+  // A cue ending before it starts is only warned about, it takes no words
+  let durations: Vec<i64> = durations.iter().map(|&duration| duration.max(0)).collect();
+  let total_duration: i64 = durations.iter().sum();
+  let mut parts = Vec::new();
+  let mut elapsed = 0;
+  let mut taken = 0;
+
+  for (i, &duration) in durations.iter().enumerate() {
+    let until = if i + 1 == durations.len() {
+      translated_words.len()
+    } else if total_duration > 0 {
+      elapsed += duration;
+      ((translated_words.len() as i64 * elapsed / total_duration) as usize).min(translated_words.len())
+    } else {
+      translated_words.len() * (i + 1) / durations.len()
+    };
+
+    parts.push(translated_words[taken..until.max(taken)].join(" "));
+    taken = until.max(taken);
+  }
+
+  parts
+}
+
+// A short flagged group is joined with the next group, or with the previous one at the end,
+// so that it gets some context. The joined translation is split back like a sentence.
+fn merge_short_groups(subs: &[Sub], groups: Vec<Vec<usize>>, min_chars: usize) -> Vec<Vec<usize>> {
+  let group_len = |group: &[usize]| group.iter().map(|&i| subs[i].text.chars().count()).sum::<usize>();
+  let mut merged_groups: Vec<Vec<usize>> = Vec::new();
+  let mut groups = groups.into_iter().peekable();
+
+  while let Some(mut group) = groups.next() {
+    let len = group_len(&group);
+
+    if len > 0 && len < min_chars && group.iter().any(|&i| subs[i].need_translation) {
+      if let Some(next_group) = groups.next_if(|next_group| group_len(next_group) > 0) {
+        group.extend(next_group);
+      } else if let Some(prev_group) = merged_groups.last_mut().filter(|prev_group| group_len(prev_group) > 0) {
+        prev_group.extend(group);
+        continue;
+      }
+    }
+
+    merged_groups.push(group);
+  }
+
+  merged_groups
+}
+
+// A sub of several lines each starting with a dash has a line per speaker
+fn is_dialogue(text: &str) -> bool {
+  text.lines().count() > 1 && text.lines().all(|line| line.trim_start().starts_with('-'))
+}
+
+// Lines of a dialogue are joined for translation, so dashes after a space start the lines of the other speakers again.
+// A dash in the middle of a line like "Wait - what?" is no speaker, the ones ending a sentence before them are taken first.
+fn split_dialogue(text: &str, speakers: usize) -> String {
+  let dashes: Vec<(usize, usize)> = Regex::new(r"\s+-\s+").unwrap().find_iter(text).map(|m| (m.start(), m.end())).collect();
+  let after_sentence: Vec<(usize, usize)> = dashes.iter()
+    .filter(|&&(start, _)| text[..start].ends_with(['.', '?', '!', '…', '"', '»', ')']))
+    .cloned()
+    .collect();
+  let mut speaker_dashes = if after_sentence.len() + 1 >= speakers { after_sentence } else { dashes };
+  speaker_dashes.truncate(speakers.saturating_sub(1));
+
+  let mut lines = Vec::new();
+  let mut line_start = 0;
+
+  for (start, end) in speaker_dashes {
+    lines.push(&text[line_start..start]);
+    line_start = end;
+  }
+
+  lines.push(&text[line_start..]);
+  lines.join("\r\n- ")
+}
+
+fn normalize_whitespace(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_known(word: &Word, options: &TranslateOptions) -> bool {
+  match (&word.kind, word.weight, options.confidence_threshold) {
+    (WordKind::Known, Some(weight), Some(threshold)) => weight >= threshold,
+    (WordKind::Known, _, _) => true,
+    _ => false,
+  }
+}
+
+fn explain_word(word_classes: &mut Vec<String>, options: &TranslateOptions, word: &str, class: &str) {
+  if options.explain {
+    word_classes.push(format!("{}: {}", word, class));
+  }
+}
+
+fn is_word_char(c: char) -> bool {
+  c.is_ascii_alphabetic() || c == '\''
+}
+
+// Byte ranges of the database phrases found as whole words in the text, longer phrases win over the ones they overlap
+fn find_phrases<'a, 'b>(lowercase_text: &str, phrases: &[&'b Word<'a>]) -> Vec<(usize, usize, &'b Word<'a>)> {
+  let mut spans: Vec<(usize, usize, &Word)> = Vec::new();
+
+  for &phrase in phrases.iter() {
+    for (start, _) in lowercase_text.match_indices(phrase.text) {
+      let end = start + phrase.text.len();
+      let is_whole = !lowercase_text[..start].ends_with(is_word_char) && !lowercase_text[end..].starts_with(is_word_char);
+
+      if is_whole && spans.iter().all(|&(span_start, span_end, _)| end <= span_start || start >= span_end) {
+        spans.push((start, end, phrase));
+      }
+    }
+  }
+
+  spans
+}
+
+pub(crate) fn highlight_tags(options: &TranslateOptions) -> (String, &'static str) {
+  match options.highlight_markup {
+    HighlightMarkup::Font => (format!("<font color=\"{}\">", options.highlight_color), "</font>"),
+    HighlightMarkup::Span => (format!("<span style=\"color:{}\">", options.highlight_color), "</span>"),
+    HighlightMarkup::Bold => (String::from("<b>"), "</b>"),
+    HighlightMarkup::Underline => (String::from("<u>"), "</u>"),
+    HighlightMarkup::None => (String::new(), ""),
+  }
+}
+
+// The word and its lookup key between private use marks, the gloss is only known once the words are translated
+fn gloss_placeholder(highlighted_word: &str, key: &str) -> String {
+  format!("\u{E000}{}\u{E001}{}\u{E002}", highlighted_word, key)
+}
+
+// A word without a gloss keeps its highlighting alone
+fn fill_glosses(text: &str, glosses: &HashMap<String, String>, gloss_format: &str) -> String {
+  let re_placeholder = Regex::new("\u{E000}([^\u{E001}]*)\u{E001}([^\u{E002}]*)\u{E002}").unwrap();
+
+  re_placeholder.replace_all(text, |caps: &Captures| match glosses.get(&caps[2]) {
+    Some(gloss) => gloss_format.replace("{word}", &caps[1]).replace("{gloss}", gloss),
+    None => String::from(&caps[1]),
+  }).into_owned()
+}
+
+fn highlight(word: &str, options: &TranslateOptions) -> String {
+  let (open_tag, close_tag) = highlight_tags(options);
+  format!("{}{}{}", open_tag, word, close_tag)
+}
+
+// Bold and underline are common in regular subs, so only the metadata sub tells such output apart
+pub fn is_translated_output(subs_text: &str, options: &TranslateOptions) -> bool {
+  let (open_tag, _) = highlight_tags(options);
+  let is_distinct_markup = !matches!(options.highlight_markup, HighlightMarkup::Bold | HighlightMarkup::Underline | HighlightMarkup::None);
+
+  subs_text.contains(METADATA_MARKER) || (is_distinct_markup && subs_text.contains(&open_tag))
+}
+
+pub struct TranslationStats {
+  pub chunks: usize,
+  pub suspicious_chunks: usize,
+  pub skipped_chunks: usize,
+  pub failed_chunks: Vec<usize>,
+  pub passthrough_groups: usize,
+  pub already_target_subs: usize,
+  pub reused_subs: usize,
+  pub explanations: Vec<String>,
+  pub traces: Vec<CueTrace>,
+}
+
+// Translation shorter than this part of the source usually means the backend failed silently
+const MIN_TRANSLATION_RATIO: f64 = 0.2;
+
+// Single words are often names the backend rightly keeps as is, longer text coming back unchanged was not translated
+const MIN_PASSTHROUGH_WORDS: usize = 2;
+
+fn is_passthrough(source_text: &str, translated_text: &str) -> bool {
+  source_text.split_whitespace().count() >= MIN_PASSTHROUGH_WORDS &&
+    normalize_whitespace(source_text).eq_ignore_ascii_case(&normalize_whitespace(translated_text))
+}
+
+const EMPTY_TRANSLATION_RETRIES: usize = 2;
+const FAILED_TRANSLATION_RETRIES: u32 = 2;
+
+fn translate_chunk<T>(translator: &T, chunk: String, options: &TranslateOptions, stats: &mut TranslationStats) -> Result<String>
+  where T: ETranslate {
+  let source_len = chunk.chars().count();
+  stats.chunks += 1;
+  let mut attempt = 0;
+  let mut failed_attempt = 0;
+
+  // A blank response would shift the translations of the following subs, so it is a failure to retry
+  let translated_chunk = loop {
+    let translated_chunk = match translator.translate(chunk.clone(), options.source_language, options.target_language) {
+      Some(translated_chunk) => translated_chunk,
+      None if failed_attempt < FAILED_TRANSLATION_RETRIES => {
+        warning(&format!("translation of chunk {} failed, retrying", stats.chunks));
+        sleep(Duration::from_secs(1 << failed_attempt));
+        failed_attempt += 1;
+        continue;
+      }
+      None => return Err(Error::Translate {
+        chunk: stats.chunks,
+        source: None,
+      }),
+    };
+
+    if !translated_chunk.trim().is_empty() || chunk.trim().is_empty() {
+      break translated_chunk;
+    }
+
+    if attempt == EMPTY_TRANSLATION_RETRIES {
+      return Err(Error::Translate {
+        chunk: stats.chunks,
+        source: Some(Box::from("the translation is empty")),
+      });
+    }
+
+    attempt += 1;
+    warning(&format!("translation of chunk {} is empty, retrying", stats.chunks));
+  };
+  let translated_len = translated_chunk.chars().count();
+
+  if (translated_len as f64) < source_len as f64 * MIN_TRANSLATION_RATIO {
+    stats.suspicious_chunks += 1;
+    warning(&format!("translation of chunk {} is suspiciously short ({} of {} chars)",
+                     stats.chunks, translated_len, source_len));
+  }
+
+  Ok(translated_chunk)
+}
+
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 4000;
+
+// Words go one per line, as many per request as fit in a chunk
+fn translate_words<T>(translator: &T, words: &[&str], options: &TranslateOptions, stats: &mut TranslationStats) -> Result<HashMap<String, String>>
+  where T: ETranslate {
+  let mut glosses = HashMap::new();
+  let mut remaining_words = words;
+
+  while !remaining_words.is_empty() {
+    let mut chunk_size = 0;
+    let count = remaining_words.iter()
+      .take_while(|word| {
+        chunk_size += word.len() + 2;
+        chunk_size <= options.max_chunk_size
+      })
+      .count()
+      .max(1);
+    let (chunk_words, rest) = remaining_words.split_at(count);
+    remaining_words = rest;
+
+    if options.max_requests.is_some_and(|max_requests| stats.chunks >= max_requests) {
+      stats.skipped_chunks += 1;
+      continue;
+    }
+
+    if stats.chunks > 0 {
+      sleep(options.chunk_delay);
+    }
+
+    let translated_chunk = translate_chunk(translator, chunk_words.join("\r\n") + "\r\n", options, stats)?.replace("\\r\\n", "\r\n");
+    let translated_words: Vec<&str> = translated_chunk.lines().collect();
+
+    if translated_words.len() != chunk_words.len() {
+      warning(&format!("translation of chunk {} does not match its words, their notes are skipped", stats.chunks));
+      continue;
+    }
+
+    for (word, translated_word) in chunk_words.iter().zip(translated_words) {
+      glosses.insert(String::from(*word), String::from(translated_word.trim()));
+    }
+  }
+
+  Ok(glosses)
+}
+
+// Each line of a chunk holds the text of one group of subs, lines are separated by "\r\n"
+pub struct Chunk {
+  pub groups: Vec<Vec<usize>>,
+  pub offsets: Vec<usize>,
+  pub text: String,
+}
+
+// A group longer than max_size still gets a chunk of its own
+pub fn build_chunks(subs: &[Sub], groups: &[Vec<usize>], max_size: usize) -> Vec<Chunk> {
+  let re_newline = Regex::new("(\r?\n)").unwrap();
+  let mut chunks = Vec::new();
+  let mut current_chunk = Chunk {
+    groups: Vec::new(),
+    offsets: Vec::new(),
+    text: String::new(),
+  };
+  let mut current_chunk_size = 0;
+
+  for group in groups.iter() {
+    let group_text = group.iter().map(|&i| subs[i].text.as_str()).collect::<Vec<_>>().join(" ");
+    let text: String = re_newline.replace_all(group_text.as_str(), "*").into();
+    current_chunk_size += text.len();
+
+    if current_chunk_size > max_size && !current_chunk.groups.is_empty() {
+      current_chunk_size = text.len();
+      chunks.push(std::mem::replace(&mut current_chunk, Chunk {
+        groups: Vec::new(),
+        offsets: Vec::new(),
+        text: String::new(),
+      }));
+    }
+
+    current_chunk.offsets.push(current_chunk.text.len());
+    current_chunk.groups.push(group.clone());
+    current_chunk.text.push_str(text.as_str());
+    current_chunk.text.push_str("\r\n");
+  }
+
+  if !current_chunk.groups.is_empty() {
+    chunks.push(current_chunk);
+  }
+
+  chunks
+}
+
+pub fn translate_subs<T>(subs: &mut [Sub], words: &HashMap<&str, Word>, translator: &T, options: &TranslateOptions) -> Result<TranslationStats>
+  where T: ETranslate {
+  translate_subs_with_progress(subs, words, translator, options, |_| {})
+}
+
+// on_chunk gets all the subs each time a chunk is translated, so they can be saved as they go
+pub fn translate_subs_with_progress<T, F>(subs: &mut [Sub], words: &HashMap<&str, Word>, translator: &T, options: &TranslateOptions, mut on_chunk: F) -> Result<TranslationStats>
+  where T: ETranslate, F: FnMut(&[Sub]) {
+  let re_color = language_letters_regex(options.source_language, options.case_locale);
+  let re_newline = Regex::new("(\r?\n)").unwrap();
+  let re_clean_tags = Regex::new("(</?[ib]>)").unwrap();
+  let re_ass_override = ass_override_regex();
+  let re_karaoke = karaoke_regex();
+  let re_leading_overrides = Regex::new(r"^(?:\{[^}]*\})+").unwrap();
+  let re_trailing_overrides = Regex::new(r"(?:\{[^}]*\})+$").unwrap();
+  let mut colored_texts = Vec::new();
+  let mut tagged_texts = Vec::new();
+  let mut override_edges = Vec::new();
+  let mut trigger_words = Vec::new();
+  let mut dialogues = Vec::new();
+  let mut reused = Vec::new();
+  let mut karaoke = Vec::new();
+  let mut phrases: Vec<&Word> = words.values().filter(|word| word.text.contains(' ')).collect();
+  phrases.sort_by(|left, right| right.text.len().cmp(&left.text.len()).then(left.text.cmp(right.text)));
+  let mut stats = TranslationStats {
+    chunks: 0,
+    suspicious_chunks: 0,
+    skipped_chunks: 0,
+    failed_chunks: Vec::new(),
+    passthrough_groups: 0,
+    already_target_subs: 0,
+    reused_subs: 0,
+    explanations: Vec::new(),
+    traces: Vec::new(),
+  };
+
+  for sub in subs.iter_mut() {
+    let mut sub_trigger_words = Vec::new();
+
+    // Translating karaoke text would break its timing, so it is kept verbatim
+    let is_karaoke_sub = !options.translate_karaoke && is_karaoke(&sub.text, &re_karaoke);
+    karaoke.push(is_karaoke_sub);
+
+    if is_karaoke_sub {
+      log_event("warning", &format!("sub {} has karaoke timing tags and is left untranslated", sub.index), None, Some(sub.index));
+
+      if options.explain {
+        stats.explanations.push(format!("Sub {}: not translated, karaoke timing tags", sub.index));
+      }
+
+      dialogues.push(0);
+      override_edges.push((String::new(), String::new()));
+      tagged_texts.push(None);
+      reused.push(false);
+      colored_texts.push(None);
+      trigger_words.push(sub_trigger_words);
+      continue;
+    }
+
+    // The number of speakers of a dialogue, none for other subs
+    dialogues.push(if options.dialogue_dashes && is_dialogue(&sub.text) { sub.text.lines().count() } else { 0 });
+    sub.text = re_clean_tags.replace_all(sub.text.as_str(), "").into();
+    sub.text = re_newline.replace_all(sub.text.as_str(), " ").into();
+
+    if options.normalize_text {
+      sub.text = normalize_whitespace(&sub.text);
+    }
+
+    if options.strip_ass_tags {
+      sub.text = re_ass_override.replace_all(sub.text.as_str(), "").into();
+      sub.position_tags.clear();
+    }
+
+    // Override blocks are no words, the original keeps them in place and the translation gets the ones around the text
+    let override_spans: Vec<(usize, usize)> = re_ass_override.find_iter(&sub.text).map(|m| (m.start(), m.end())).collect();
+    override_edges.push(ass_override_edges(&sub.text, &re_leading_overrides, &re_trailing_overrides));
+
+    // Phrases are matched first, so the words of a known phrase are not looked up one by one
+    // Spans are byte ranges of the text, so folding changing the length falls back to ASCII lowercase
+    let lowercase_text = Some(fold_case(&sub.text, options.source_language, options.case_locale))
+      .filter(|lowercase_text| lowercase_text.len() == sub.text.len())
+      .unwrap_or_else(|| sub.text.to_ascii_lowercase());
+    let phrase_spans = find_phrases(&lowercase_text, &phrases);
+    let mut triggered_phrases = HashSet::new();
+    let url_spans = if options.translate_urls { Vec::new() } else { non_linguistic_spans(&sub.text) };
+    let mut word_classes = Vec::new();
+
+    let colored_text: String = re_color.replace_all(sub.text.as_str(), |caps: &Captures| {
+      let captured_word = caps.get(0).unwrap().as_str();
+      let position = caps.get(0).unwrap().start();
+
+      if override_spans.iter().any(|&(start, end)| position >= start && position < end) {
+        return String::from(captured_word);
+      }
+
+      if url_spans.iter().any(|&(start, end)| position >= start && position < end) {
+        explain_word(&mut word_classes, options, captured_word, "url");
+        return String::from(captured_word);
+      }
+
+      if let Some(&(start, _, phrase)) = phrase_spans.iter().find(|&&(start, end, _)| position >= start && position < end) {
+        if is_known(phrase, options) {
+          explain_word(&mut word_classes, options, captured_word, "in known phrase");
+          return String::from(captured_word);
+        }
+
+        explain_word(&mut word_classes, options, captured_word, "in unknown phrase");
+
+        // The gloss of a phrase goes over its first word only
+        if triggered_phrases.insert(start) {
+          sub_trigger_words.push(String::from(phrase.text));
+
+          if options.gloss_format.is_some() {
+            return gloss_placeholder(&highlight(captured_word, options), phrase.text);
+          }
+        }
+
+        return highlight(captured_word, options);
+      }
+
+      let lowercase_word = fold_case(captured_word, options.source_language, options.case_locale);
+
+      if options.stopwords.contains(&lowercase_word) {
+        explain_word(&mut word_classes, options, captured_word, "stopword");
+        return String::from(captured_word);
+      }
+
+      if options.skip_acronyms && is_acronym(captured_word) {
+        explain_word(&mut word_classes, options, captured_word, "acronym");
+        return String::from(captured_word);
+      }
+
+      if let Some(word) = words.get(lowercase_word.as_str()) {
+        if !is_known(word, options) {
+          explain_word(&mut word_classes, options, captured_word, word.kind.name());
+
+          if options.gloss_format.is_some() {
+            let placeholder = gloss_placeholder(&highlight(captured_word, options), &lowercase_word);
+            sub_trigger_words.push(lowercase_word);
+            return placeholder;
+          }
+
+          sub_trigger_words.push(lowercase_word);
+
+          return highlight(captured_word, options);
+        }
+
+        explain_word(&mut word_classes, options, captured_word, "known");
+      } else {
+        explain_word(&mut word_classes, options, captured_word, "not in database");
+      }
+
+      String::from(captured_word)
+    }).into();
+
+    if override_spans.is_empty() {
+      tagged_texts.push(None);
+    } else {
+      let plain_text = normalize_whitespace(&re_ass_override.replace_all(sub.text.as_str(), " "));
+      tagged_texts.push(Some(std::mem::replace(&mut sub.text, plain_text)));
+    }
+
+    let matches_filter = options.text_filter.as_ref().is_none_or(|re| re.is_match(&sub.text));
+    let in_index_range = options.index_from.is_none_or(|from| sub.index >= from) && options.index_to.is_none_or(|to| sub.index <= to);
+
+    let has_enough_unknown = sub_trigger_words.len() >= options.min_unknown.max(1) ||
+      (options.translate_all && !sub.text.is_empty() && (options.translate_urls || !is_non_linguistic(&sub.text)));
+    let mut is_target_language = false;
+
+    if matches_filter && in_index_range && has_enough_unknown {
+      is_target_language = options.detect_skip &&
+        detect_language(&sub.text).is_some_and(|language| language.to_string() == options.target_language.to_string());
+
+      if is_target_language {
+        stats.already_target_subs += 1;
+      } else {
+        sub.need_translation = true;
+      }
+    }
+
+    if options.explain {
+      let decision = if sub.need_translation {
+        String::from("translated")
+      } else if !matches_filter {
+        String::from("not translated, text does not match")
+      } else if !in_index_range {
+        String::from("not translated, index out of range")
+      } else if is_target_language {
+        String::from("not translated, already in the target language")
+      } else {
+        format!("not translated, {} unknown words of {} needed", sub_trigger_words.len(), options.min_unknown.max(1))
+      };
+
+      stats.explanations.push(format!("Sub {}: {} [{}]", sub.index, decision, word_classes.join(", ")));
+    }
+
+    // An unchanged sub keeps the translation of the earlier output instead of going to the translator
+    let previous_translation = options.previous_cues.get(&(sub.index, sub.start_time, sub.end_time))
+      .filter(|previous_cue| sub.need_translation && previous_cue.text == strip_markup(&sub.text))
+      .and_then(|previous_cue| previous_cue.translation.clone());
+
+    if previous_translation.is_some() {
+      stats.reused_subs += 1;
+    }
+
+    reused.push(previous_translation.is_some());
+    sub.translation = previous_translation;
+    colored_texts.push(Some(colored_text));
+    trigger_words.push(sub_trigger_words);
+  }
+
+  let groups = if options.sentence_mode {
+    group_sentences(subs)
+  } else {
+    (0..subs.len()).map(|i| vec![i]).collect()
+  };
+
+  // Unflagged groups joined to a short one are its context, they get no part of the translation
+  let context_subs: HashSet<usize> = match options.merge_short {
+    Some(_) => groups.iter().filter(|group| !group.iter().any(|&i| subs[i].need_translation)).flatten().cloned().collect(),
+    None => HashSet::new(),
+  };
+
+  let groups = match options.merge_short {
+    Some(min_chars) => merge_short_groups(subs, groups, min_chars),
+    None => groups,
+  };
+
+  let groups: Vec<Vec<usize>> = groups.into_iter()
+    .map(|group| group.into_iter().filter(|&i| !karaoke[i]).collect::<Vec<_>>())
+    .filter(|group| group.iter().any(|&i| subs[i].need_translation && !reused[i]))
+    .collect();
+
+  // Inline glosses stand in for the translation of the sentences
+  let chunks = if options.gloss_format.is_some() { Vec::new() } else { build_chunks(subs, &groups, options.max_chunk_size) };
+
+  let glosses = if options.word_notes || options.gloss_format.is_some() {
+    let mut unique_words: Vec<&str> = subs.iter().zip(trigger_words.iter())
+      .filter(|(sub, _)| sub.need_translation)
+      .flat_map(|(_, sub_trigger_words)| sub_trigger_words.iter().map(String::as_str))
+      .collect();
+    unique_words.sort();
+    unique_words.dedup();
+
+    translate_words(translator, &unique_words, options, &mut stats)?
+  } else {
+    HashMap::new()
+  };
+
+  if let Some(gloss_format) = &options.gloss_format {
+    for colored_text in colored_texts.iter_mut().flatten() {
+      *colored_text = fill_glosses(colored_text, &glosses, gloss_format);
+    }
+  }
+
+  for chunk in chunks.into_iter() {
+    // Flagged subs of chunks over the limit keep their highlighting but get no translation
+    if options.max_requests.is_some_and(|max_requests| stats.chunks >= max_requests) {
+      stats.skipped_chunks += 1;
+      continue;
+    }
+
+    if stats.chunks > 0 {
+      sleep(options.chunk_delay);
+    }
+
+    // A translator merging or splitting lines would shift every following translation
+    // The context goes as a line of its own, so its translation is the first line to drop
+    let chunk_text = match &options.context {
+      Some(context) => format!("{}\r\n{}", context, chunk.text),
+      None => chunk.text.clone(),
+    };
+    let translated_chunk = translate_chunk(translator, chunk_text, options, &mut stats)
+      .map(|translated_chunk| translated_chunk.replace("\\r\\n", "\r\n"))
+      .map(|translated_chunk| match &options.context {
+        Some(_) => translated_chunk.split_once('\n').map_or_else(String::new, |(_, rest)| String::from(rest)),
+        None => translated_chunk,
+      })
+      .and_then(|translated_chunk| match translated_chunk.lines().count() {
+        count if count == chunk.groups.len() => Ok(translated_chunk),
+        count => Err(Error::Translate {
+          chunk: stats.chunks,
+          source: Some(format!("expected {} lines, got {}", chunk.groups.len(), count).into()),
+        }),
+      });
+
+    // A failed chunk leaves its subs untranslated like the ones over the request limit
+    let translated_chunk = match translated_chunk {
+      Ok(translated_chunk) => translated_chunk,
+      Err(err) if options.skip_failed_chunks => {
+        warning(&format!("{}, its subs are left untranslated", err));
+        stats.failed_chunks.push(stats.chunks);
+        continue;
+      }
+      Err(err) => return Err(err),
+    };
+    let translated_lines: Vec<&str> = translated_chunk.lines().collect();
+
+    for (group, translated_line) in chunk.groups.iter().zip(translated_lines) {
+      let translated_text = translated_line.replace(" *", "\r\n");
+      let source_text = group.iter().map(|&i| subs[i].text.as_str()).collect::<Vec<_>>().join(" ");
+
+      if is_passthrough(&source_text, &translated_text) {
+        stats.passthrough_groups += 1;
+        log_event("warning", &format!("sub {} came back untranslated", subs[group[0]].index), None, Some(subs[group[0]].index));
+      }
+
+      let parts = if group.len() == 1 {
+        vec![translated_text]
+      } else {
+        let durations: Vec<i64> = group.iter()
+          .map(|&i| subs[i].end_time.signed_duration_since(subs[i].start_time).num_milliseconds())
+          .collect();
+
+        distribute_translation(translated_text.as_str(), &durations)
+      };
+
+      for (&i, mut part) in group.iter().zip(parts) {
+        if context_subs.contains(&i) {
+          continue;
+        }
+
+        let (leading, trailing) = &override_edges[i];
+        part = format!("{}{}{}", leading, part, trailing);
+        let mut notes: Vec<String> = Vec::new();
+
+        for word in trigger_words[i].iter() {
+          if let Some(gloss) = glosses.get(word) {
+            let note = format!("{}={}", word, gloss);
+
+            if !notes.contains(&note) {
+              notes.push(note);
+            }
+          }
+        }
+
+        if !notes.is_empty() {
+          part = format!("{}\r\n[{}]", part, notes.join(", "));
+        }
+
+        // Marks on both ends keep punctuation and embedded latin words of a line from jumping to the wrong side
+        if options.rtl_translation {
+          part = part.lines().map(|line| format!("\u{200F}{}\u{200F}", line)).collect::<Vec<_>>().join("\r\n");
+        }
+
+        let sub = &mut subs[i];
+        sub.need_translation = true;
+        stats.traces.push(CueTrace {
+          index: sub.index,
+          original: sub.text.clone(),
+          translation: part.clone(),
+          trigger_words: std::mem::take(&mut trigger_words[i]),
+        });
+        sub.translation = Some(part);
+
+        if let Some(colored_text) = colored_texts[i].take() {
+          sub.text = colored_text;
+        }
+
+        if dialogues[i] > 0 {
+          sub.text = split_dialogue(&sub.text, dialogues[i]);
+          sub.translation = sub.translation.as_deref().map(|translation| split_dialogue(translation, dialogues[i]));
+        }
+      }
+    }
+
+    on_chunk(subs);
+  }
+
+  // Chunks are built from the plain text, highlighting goes in once a sub is translated or skipped
+  for ((sub, colored_text), tagged_text) in subs.iter_mut().zip(colored_texts).zip(tagged_texts) {
+    if sub.need_translation {
+      if let Some(colored_text) = colored_text {
+        sub.text = colored_text;
+      }
+    } else if let Some(tagged_text) = tagged_text {
+      sub.text = tagged_text;
+    }
+  }
+
+  // Translated dialogues are split already
+  for (sub, speakers) in subs.iter_mut().zip(dialogues).filter(|(sub, speakers)| *speakers > 0 && !sub.is_translated()) {
+    sub.text = split_dialogue(&sub.text, speakers);
+  }
+
+  Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::{Cell, RefCell};
+
+  use super::*;
+  use crate::builder::TranslateOptionsBuilder;
+  use crate::subs::parse_subs;
+  use crate::words::parse_db_words;
+
+  // Lines are changed so they do not look like untranslated passthrough
+  struct MockTranslator;
+
+  impl ETranslate for MockTranslator {
+    fn translate(&self, text: String, _in: Langage, _out: Langage) -> Option<String> {
+      Some(text.lines().map(|line| format!("~{}", line)).collect::<Vec<_>>().join("\r\n"))
+    }
+  }
+
+  // Answers with blank text the given number of times first
+  struct EmptyTranslator {
+    empty_answers: Cell<usize>,
+  }
+
+  impl ETranslate for EmptyTranslator {
+    fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+      match self.empty_answers.get() {
+        0 => MockTranslator.translate(text, source, target),
+        count => {
+          self.empty_answers.set(count - 1);
+          Some(String::new())
+        }
+      }
+    }
+  }
+
+  fn options() -> TranslateOptionsBuilder {
+    TranslateOptions::builder(Langage::EN, Langage::RU).chunk_delay(Duration::from_secs(0))
+  }
+
+  fn single_sub(text: &str) -> Vec<Sub> {
+    parse_subs(&format!("1\r\n00:00:01,000 --> 00:00:02,000\r\n{}\r\n\r\n", text)).unwrap()
+  }
+
+  // Subs of the given durations in ms, each one starting when the previous one ends
+  fn timed_subs(cues: &[(&str, u32)]) -> Vec<Sub> {
+    let mut start = 0;
+    let subs_text = cues.iter().enumerate().fold(String::new(), |acc, (i, &(text, duration))| {
+      let time = |ms: u32| format!("00:{:02}:{:02},{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000);
+      let cue = format!("{}\r\n{} --> {}\r\n{}\r\n\r\n", i + 1, time(start), time(start + duration), text);
+      start += duration;
+      acc + &cue
+    });
+
+    parse_subs(&subs_text).unwrap()
+  }
+
+  #[test]
+  fn sentence_spanning_three_cues_is_one_group() {
+    let subs = timed_subs(&[("When the night comes", 1000), ("and the stars are out,", 1000), ("we leave.", 2000), ("Fine.", 1000)]);
+
+    assert_eq!(group_sentences(&subs), vec![vec![0, 1, 2], vec![3]]);
+  }
+
+  #[test]
+  fn translation_is_split_by_cue_durations() {
+    let parts = distribute_translation("one two three four five six seven eight", &[1000, 1000, 2000]);
+
+    assert_eq!(parts, vec!["one two", "three four", "five six seven eight"]);
+  }
+
+  #[test]
+  fn dialogue_lines_are_split_by_speaker() {
+    let words = parse_db_words("u:ship\r\n");
+    let mut subs = single_sub("- Where is the ship?\r\n- Gone.");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().dialogue_dashes(true).build()).unwrap();
+
+    assert_eq!(subs[0].text, "- Where is the <font color=\"#FFFF80\">ship</font>?\r\n- Gone.");
+    assert_eq!(subs[0].translation.as_deref(), Some("~- Where is the ship?\r\n- Gone."));
+  }
+
+  #[test]
+  fn dash_inside_a_line_is_no_speaker() {
+    let words = parse_db_words("u:ship\r\n");
+    let mut subs = single_sub("- The ship - wait - what?\r\n- Gone.");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().dialogue_dashes(true).build()).unwrap();
+
+    assert_eq!(subs[0].translation.as_deref(), Some("~- The ship - wait - what?\r\n- Gone."));
+
+    let mut subs = single_sub("The ship - wait - what?");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().dialogue_dashes(true).build()).unwrap();
+
+    assert_eq!(subs[0].translation.as_deref(), Some("~The ship - wait - what?"));
+  }
+
+  #[test]
+  fn subs_of_skipped_chunks_are_not_translated() {
+    let words = parse_db_words("u:ship\r\n");
+    let mut subs = single_sub("The ship sails");
+    let stats = translate_subs(&mut subs, &words, &MockTranslator, &options().max_requests(0).build()).unwrap();
+
+    assert_eq!(stats.skipped_chunks, 1);
+    assert!(!subs[0].is_translated());
+    assert_eq!(subs[0].text, "The <font color=\"#FFFF80\">ship</font> sails");
+  }
+
+  #[test]
+  fn inverted_cue_in_a_group_takes_no_words() {
+    let parts = distribute_translation("one two three four five six", &[3000, -2000, 1000]);
+
+    assert_eq!(parts, vec!["one two three four", "", "five six"]);
+  }
+
+  #[test]
+  fn sentence_with_an_inverted_cue_is_translated() {
+    let words = parse_db_words("u:ship\r\n");
+    let mut subs = parse_subs("\
+1\r\n00:00:01,000 --> 00:00:04,000\r\nWhen the ship\r\n\r\n\
+2\r\n00:00:07,000 --> 00:00:05,000\r\nis ready\r\n\r\n\
+3\r\n00:00:08,000 --> 00:00:09,000\r\nwe sail.\r\n\r\n").unwrap();
+    translate_subs(&mut subs, &words, &MockTranslator, &options().sentence_mode(true).build()).unwrap();
+
+    let translations: Vec<Option<&str>> = subs.iter().map(|sub| sub.translation.as_deref()).collect();
+    assert_eq!(translations, vec![Some("~When the ship is ready"), Some(""), Some("we sail.")]);
+  }
+
+  #[test]
+  fn chunks_end_before_exceeding_the_size_limit() {
+    let subs = timed_subs(&[("Alpha", 1000), ("Bravo", 1000), ("Charlie", 1000)]);
+    let chunks = build_chunks(&subs, &[vec![0], vec![1], vec![2]], 10);
+
+    let chunk_groups: Vec<&Vec<Vec<usize>>> = chunks.iter().map(|chunk| &chunk.groups).collect();
+    assert_eq!(chunk_groups, vec![&vec![vec![0], vec![1]], &vec![vec![2]]]);
+    assert_eq!(chunks[0].text, "Alpha\r\nBravo\r\n");
+    assert_eq!(chunks[0].offsets, vec![0, 7]);
+    assert_eq!(chunks[1].text, "Charlie\r\n");
+  }
+
+  #[test]
+  fn oversized_group_gets_a_chunk_of_its_own() {
+    let subs = timed_subs(&[("Hi", 1000), ("A line far longer than the limit", 1000), ("Bye", 1000)]);
+    let chunks = build_chunks(&subs, &[vec![0], vec![1], vec![2]], 8);
+
+    let chunk_texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+    assert_eq!(chunk_texts, vec!["Hi\r\n", "A line far longer than the limit\r\n", "Bye\r\n"]);
+  }
+
+  #[test]
+  fn max_chunk_size_counts_the_text_without_line_breaks() {
+    struct CountingTranslator(Cell<usize>);
+
+    impl ETranslate for CountingTranslator {
+      fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+        self.0.set(self.0.get() + 1);
+        MockTranslator.translate(text, source, target)
+      }
+    }
+
+    let words = parse_db_words("u:ship\r\nu:sail\r\n");
+    let requests = |max_chunk_size: usize| {
+      let mut subs = timed_subs(&[("The ship", 1000), ("We sail", 1000)]);
+      let translator = CountingTranslator(Cell::new(0));
+      translate_subs(&mut subs, &words, &translator, &options().max_chunk_size(max_chunk_size).build()).unwrap();
+      translator.0.get()
+    };
+
+    assert_eq!(requests(15), 1);
+    assert_eq!(requests(14), 2);
+  }
+
+  #[test]
+  fn valid_colors_are_accepted() {
+    assert_eq!(parse_color("#80ff80"), Ok(String::from("#80ff80")));
+    assert_eq!(parse_color("#A0B1C2"), Ok(String::from("#A0B1C2")));
+    assert_eq!(parse_color("Cyan"), Ok(String::from("#00FFFF")));
+  }
+
+  #[test]
+  fn colors_of_wrong_length_are_rejected() {
+    assert!(parse_color("#FFF").is_err());
+    assert!(parse_color("#FFFFFF00").is_err());
+    assert!(parse_color("").is_err());
+  }
+
+  #[test]
+  fn non_hex_colors_are_rejected() {
+    assert!(parse_color("#GGHHII").is_err());
+    assert!(parse_color("FFFF80").is_err());
+    assert!(parse_color("purple").is_err());
+  }
+
+  #[test]
+  fn tags_and_extra_spaces_are_normalized() {
+    let mut subs = single_sub("<i>Hi</i>  there ");
+    translate_subs(&mut subs, &HashMap::new(), &MockTranslator, &options().build()).unwrap();
+
+    assert_eq!(subs[0].text, "Hi there");
+  }
+
+  #[test]
+  fn raw_spacing_is_kept_without_normalizing() {
+    let mut subs = single_sub("<i>Hi</i>  there ");
+    translate_subs(&mut subs, &HashMap::new(), &MockTranslator, &options().normalize_text(false).build()).unwrap();
+
+    assert_eq!(subs[0].text, "Hi  there ");
+  }
+
+  #[test]
+  fn pair_of_short_cues_is_merged() {
+    let mut subs = timed_subs(&[("Hi.", 1000), ("Yes.", 1000), ("That was a much longer sentence.", 2000)]);
+    subs[0].need_translation = true;
+
+    assert_eq!(merge_short_groups(&subs, vec![vec![0], vec![1], vec![2]], 10), vec![vec![0, 1], vec![2]]);
+  }
+
+  #[test]
+  fn merged_neighbour_is_context_only() {
+    struct RecordingTranslator(RefCell<Vec<String>>);
+
+    impl ETranslate for RecordingTranslator {
+      fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+        self.0.borrow_mut().push(text.clone());
+        MockTranslator.translate(text, source, target)
+      }
+    }
+
+    let words = parse_db_words("u:ship\r\nk:the\r\nk:far\r\nk:away\r\n");
+    let mut subs = timed_subs(&[("The ship!", 1000), ("Far away.", 1000)]);
+    let translator = RecordingTranslator(RefCell::new(Vec::new()));
+    translate_subs(&mut subs, &words, &translator, &options().merge_short(10).build()).unwrap();
+
+    assert_eq!(*translator.0.borrow(), vec!["The ship! Far away.\r\n"]);
+    assert_eq!(subs[0].translation.as_deref(), Some("~The ship!"));
+    assert!(subs[1].translation.is_none());
+    assert!(!subs[1].need_translation);
+    assert_eq!(subs[1].text, "Far away.");
+  }
+
+  #[test]
+  fn known_phrase_keeps_its_words_from_being_highlighted() {
+    let words = parse_db_words("k:of course\r\nu:course\r\nk:of\r\nk:the\r\nk:is\r\nk:long\r\n");
+    let mut subs = parse_subs("1\r\n00:00:01,000 --> 00:00:02,000\r\nOf course\r\n\r\n2\r\n00:00:03,000 --> 00:00:04,000\r\nThe course is long\r\n\r\n").unwrap();
+    translate_subs(&mut subs, &words, &MockTranslator, &options().build()).unwrap();
+
+    assert_eq!(find_phrases("of course", &[words.get("of course").unwrap()]).len(), 1);
+    assert_eq!(subs[0].text, "Of course");
+    assert!(subs[0].translation.is_none());
+    assert_eq!(subs[1].text, "The <font color=\"#FFFF80\">course</font> is long");
+  }
+
+  #[test]
+  fn empty_answer_is_retried() {
+    let words = parse_db_words("u:ship\r\n");
+    let mut subs = single_sub("The ship");
+    let translator = EmptyTranslator { empty_answers: Cell::new(1) };
+    let stats = translate_subs(&mut subs, &words, &translator, &options().build()).unwrap();
+
+    assert_eq!(stats.chunks, 1);
+    assert_eq!(subs[0].translation.as_deref(), Some("~The ship"));
+  }
+
+  #[test]
+  fn always_empty_answer_fails_the_chunk() {
+    let words = parse_db_words("u:ship\r\n");
+    let mut subs = single_sub("The ship");
+    let translator = EmptyTranslator { empty_answers: Cell::new(EMPTY_TRANSLATION_RETRIES + 1) };
+
+    assert!(translate_subs(&mut subs, &words, &translator, &options().build()).is_err());
+  }
+
+  #[test]
+  fn ass_overrides_are_kept_unless_stripped() {
+    let words = parse_db_words("u:hello\r\n");
+    let mut subs = single_sub("{\\an8}{\\i1}Hello{\\i0}");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().build()).unwrap();
+
+    assert_eq!(subs[0].position_tags, "{\\an8}");
+    assert_eq!(subs[0].text, "{\\i1}<font color=\"#FFFF80\">Hello</font>{\\i0}");
+    assert_eq!(subs[0].translation.as_deref(), Some("{\\i1}~Hello{\\i0}"));
+
+    let mut subs = single_sub("{\\an8}{\\i1}Hello{\\i0}");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().strip_ass_tags(true).build()).unwrap();
+
+    assert_eq!(subs[0].position_tags, "");
+    assert_eq!(subs[0].text, "<font color=\"#FFFF80\">Hello</font>");
+    assert_eq!(subs[0].translation.as_deref(), Some("~Hello"));
+  }
+
+  #[test]
+  fn karaoke_cue_is_left_untranslated() {
+    let re_karaoke = karaoke_regex();
+
+    assert!(is_karaoke("{\\k20}Shine {\\k35}bright", &re_karaoke));
+    assert!(is_karaoke("<00:01.000>Shine <00:01.500>bright", &re_karaoke));
+    assert!(!is_karaoke("{\\k20}Shine bright", &re_karaoke));
+    assert!(!is_karaoke("{\\i1}Shine{\\i0} bright", &re_karaoke));
+
+    let words = parse_db_words("u:shine\r\nu:bright\r\n");
+    let mut subs = single_sub("{\\k20}Shine {\\k35}bright");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().build()).unwrap();
+
+    assert_eq!(subs[0].text, "{\\k20}Shine {\\k35}bright");
+    assert!(subs[0].translation.is_none());
+  }
+
+  #[test]
+  fn context_line_is_sent_and_stripped() {
+    struct RecordingTranslator(RefCell<Vec<String>>);
+
+    impl ETranslate for RecordingTranslator {
+      fn translate(&self, text: String, source: Langage, target: Langage) -> Option<String> {
+        self.0.borrow_mut().push(text.clone());
+        MockTranslator.translate(text, source, target)
+      }
+    }
+
+    let words = parse_db_words("u:ship\r\n");
+    let mut subs = single_sub("The ship sails");
+    let translator = RecordingTranslator(RefCell::new(Vec::new()));
+    translate_subs(&mut subs, &words, &translator, &options().context("A pirate movie").build()).unwrap();
+
+    assert_eq!(*translator.0.borrow(), vec!["A pirate movie\r\nThe ship sails\r\n"]);
+    assert_eq!(subs[0].translation.as_deref(), Some("~The ship sails"));
+  }
+
+  #[test]
+  fn ruby_markup_puts_the_gloss_over_the_word() {
+    let words = parse_db_words("k:the\r\nu:ship\r\nk:sails\r\n");
+    let mut subs = single_sub("The ship sails");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().gloss_format(RUBY_GLOSS_FORMAT).build()).unwrap();
+
+    assert_eq!(subs[0].text, "The <ruby><font color=\"#FFFF80\">ship</font><rt>~ship</rt></ruby> sails");
+    assert!(subs[0].translation.is_none());
+  }
+
+  #[test]
+  fn previous_dialogue_original_is_read_whole() {
+    let previous_subs = parse_subs("\
+0\n00:00:00,000 --> 00:00:00,000\n[srt-translator] order=original-first dialogue=1:2\n\n\
+1\n00:00:01,000 --> 00:00:02,000\n- Hi <font color=\"#FFFF80\">there</font>.\n- Bye.\n- Привет.\n- Пока.\n\n").unwrap();
+    let previous_cues = parse_previous_cues(&previous_subs).unwrap();
+    let previous_cue = previous_cues.values().next().unwrap();
+
+    assert_eq!(previous_cue.text, "- Hi there. - Bye.");
+    assert_eq!(previous_cue.translation.as_deref(), Some("- Привет.\r\n- Пока."));
+  }
+
+  #[test]
+  fn rtl_translation_lines_are_wrapped_in_marks() {
+    let words = parse_db_words("u:hello\r\nu:world\r\n");
+    let mut subs = single_sub("Hello, world!");
+    translate_subs(&mut subs, &words, &MockTranslator, &options().rtl_translation(true).build()).unwrap();
+
+    assert_eq!(subs[0].translation.as_deref(), Some("\u{200F}~Hello, world!\u{200F}"));
+  }
+}
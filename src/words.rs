@@ -0,0 +1,368 @@
+use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+use translate_core::Langage;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WordKind {
+  Known,
+  Unknown,
+  New,
+}
+
+impl WordKind {
+  pub fn name(&self) -> &'static str {
+    match self {
+      WordKind::Known => "known",
+      WordKind::Unknown => "unknown",
+      WordKind::New => "new",
+    }
+  }
+
+  pub fn code(&self) -> &'static str {
+    match self {
+      WordKind::Known => "k",
+      WordKind::Unknown => "u",
+      WordKind::New => "?",
+    }
+  }
+}
+
+pub const DEFAULT_DB_ORDER: [WordKind; 3] = [WordKind::New, WordKind::Unknown, WordKind::Known];
+
+// Kinds are given by their codes separated by commas like "?,u"
+pub fn parse_db_kinds(kinds: &str) -> std::result::Result<Vec<WordKind>, String> {
+  kinds.split(',')
+    .map(|code| code.trim().parse().map_err(|_| format!("Unknown word kind '{}'", code)))
+    .collect()
+}
+
+// Kinds are given by their codes like "?uk", "none" keeps all words in a single alphabetical list
+pub fn parse_db_order(order: &str) -> std::result::Result<Vec<WordKind>, String> {
+  if order == "none" {
+    return Ok(Vec::new());
+  }
+
+  let mut kinds = Vec::new();
+
+  for code in order.chars() {
+    let kind: WordKind = code.to_string().parse().map_err(|_| format!("Unknown word kind '{}'", code))?;
+
+    if kinds.contains(&kind) {
+      return Err(format!("Word kind '{}' is given twice", code));
+    }
+
+    kinds.push(kind);
+  }
+
+  Ok(kinds)
+}
+
+impl FromStr for WordKind {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<WordKind, Self::Err> {
+    match s {
+      "k" => Ok(WordKind::Known),
+      "u" => Ok(WordKind::Unknown),
+      "?" => Ok(WordKind::New),
+      _ => Err(String::from("Parsing error"))
+    }
+  }
+}
+
+pub struct Word<'a> {
+  pub text: &'a str,
+  pub kind: WordKind,
+  pub weight: Option<f32>,
+}
+
+impl<'a> Word<'a> {
+  pub fn weight_suffix(&self) -> String {
+    match self.weight {
+      Some(weight) => format!(":{}", weight),
+      None => String::new(),
+    }
+  }
+}
+
+pub const ENGLISH_STOPWORDS: [&str; 60] = [
+  "a", "about", "after", "all", "am", "an", "and", "are", "as", "at",
+  "be", "been", "but", "by", "can", "do", "for", "from", "had", "has",
+  "have", "he", "her", "him", "his", "i", "if", "in", "is", "it",
+  "its", "me", "my", "no", "not", "of", "on", "or", "our", "she",
+  "so", "that", "the", "their", "them", "they", "this", "to", "up", "us",
+  "was", "we", "were", "what", "will", "with", "you", "your", "yes", "oh",
+];
+
+// One word per line, empty lines and lines starting with '#' are skipped
+pub fn parse_stopwords(text: &str) -> HashSet<String> {
+  text.lines()
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(|line| line.to_lowercase())
+    .collect()
+}
+
+pub fn parse_db_words(text: &str) -> HashMap<&str, Word<'_>> {
+  let mut words = HashMap::new();
+  // TODO: make 're' const
+  let re = Regex::new(r"(?P<type>[\?ku]):(?P<text>.+?)(?::(?P<weight>\d+(?:\.\d+)?))?\r?\n").unwrap();
+
+  // TODO: replace by functional 'map' if possible
+  for caps in re.captures_iter(text) {
+    let kind: WordKind = caps.name("type").unwrap().as_str().parse().unwrap();
+    let text = caps.name("text").unwrap().as_str();
+    let weight = caps.name("weight").map(|weight| weight.as_str().parse().unwrap());
+
+    words.insert(text, Word {
+      text,
+      kind,
+      weight,
+    });
+  }
+
+  words
+}
+
+// Words are sorted by the position of their kind in kind_order, kinds missing from it go last,
+// then by text. Texts are unique, so the same words always give the same bytes.
+pub fn stringify_db_words<'a, 'b: 'a, I>(words: I, kind_order: &[WordKind]) -> String where I: IntoIterator<Item = &'a Word<'b>> {
+  let kind_rank = |kind: &WordKind| kind_order.iter().position(|order_kind| order_kind == kind).unwrap_or(kind_order.len());
+
+  let mut sorted_words: Vec<&Word> = words.into_iter().collect();
+  sorted_words.sort_by(|&left, &right| {
+    kind_rank(&left.kind).cmp(&kind_rank(&right.kind)).then(left.text.cmp(right.text))
+  });
+
+  sorted_words.iter().fold(String::new(), |s, &w| s + w.kind.code() + ":" + w.text + &w.weight_suffix() + "\r\n")
+}
+
+// New words over the limit, the ones seen most often in the subs are kept, then the first alphabetically.
+// Known and unknown words are never dropped but count towards the limit.
+pub fn excess_new_words<'a>(words: &HashMap<&'a str, Word<'a>>, max_words: usize, counts: &HashMap<&str, usize>) -> HashSet<&'a str> {
+  let classified_count = words.values().filter(|word| !matches!(word.kind, WordKind::New)).count();
+  let mut new_words: Vec<&'a str> = words.iter()
+    .filter(|(_, word)| matches!(word.kind, WordKind::New))
+    .map(|(&text, _)| text)
+    .collect();
+
+  new_words.sort_by(|left, right| {
+    let count = |text: &str| counts.get(text).cloned().unwrap_or(0);
+    count(right).cmp(&count(left)).then(left.cmp(right))
+  });
+
+  new_words.into_iter().skip(max_words.saturating_sub(classified_count)).collect()
+}
+
+// All caps words like NASA or FBI, a capitalized word starting a sentence is not one
+pub fn is_acronym(word: &str) -> bool {
+  word.len() >= 2 && word.chars().all(|c| c.is_ascii_uppercase())
+}
+
+pub fn strip_acronyms(text: &str) -> String {
+  let re_word = Regex::new("[a-zA-Z']+").unwrap();
+
+  re_word.replace_all(text, |caps: &Captures| {
+    let word = caps.get(0).unwrap().as_str();
+
+    if is_acronym(word) { String::new() } else { String::from(word) }
+  }).into_owned()
+}
+
+static NON_LINGUISTIC_REGEX: OnceLock<Regex> = OnceLock::new();
+
+// URLs, emails, file names and paths, and hashtags are kept as is, their parts are not words.
+// The Unicode classes make the regex slow to build, so it is built once for all cues.
+fn non_linguistic_regex() -> &'static Regex {
+  NON_LINGUISTIC_REGEX.get_or_init(|| Regex::new(r"(?i)(?:https?://|www\.)\S+|[\w.+-]+@[\w-]+\.[\w.-]+|(?:\b[a-z]:|\B)[\\/][\w.-]+(?:[\\/][\w.-]+)*|\b(?:[\w.-]+[\\/]){2,}[\w.-]+|\b[\w-]+\.(?:txt|srt|mp3|mp4|mkv|avi|jpg|png|gif|pdf|zip|exe|html?)\b|#\w+").unwrap())
+}
+
+pub fn non_linguistic_spans(text: &str) -> Vec<(usize, usize)> {
+  non_linguistic_regex().find_iter(text).map(|m| (m.start(), m.end())).collect()
+}
+
+// True for text having such tokens and nothing else but punctuation
+pub fn is_non_linguistic(text: &str) -> bool {
+  let stripped = strip_non_linguistic(text);
+  stripped.len() < text.len() && !stripped.chars().any(char::is_alphanumeric)
+}
+
+pub fn strip_non_linguistic(text: &str) -> String {
+  non_linguistic_regex().replace_all(text, " ").into_owned()
+}
+
+// Override blocks like {\an8} or {\i1} left in subs converted from ASS
+pub(crate) fn ass_override_regex() -> Regex {
+  Regex::new(r"\{[^}]*\}").unwrap()
+}
+
+pub fn strip_ass_overrides(text: &str) -> String {
+  ass_override_regex().replace_all(text, " ").into_owned()
+}
+
+pub fn default_word_regex() -> Regex {
+  Regex::new(r"(?msx)(?:(?P<word>[a-z']+?)[^a-z']+)").unwrap()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CaseLocale {
+  Default,
+  Turkish,
+  German,
+}
+
+impl FromStr for CaseLocale {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<CaseLocale, Self::Err> {
+    match s {
+      "default" => Ok(CaseLocale::Default),
+      "tr" => Ok(CaseLocale::Turkish),
+      "de" => Ok(CaseLocale::German),
+      _ => Err(format!("Unknown case locale '{}'", s))
+    }
+  }
+}
+
+// English is kept to ASCII, other languages need full Unicode case folding for letters like 'Ä' or 'É'.
+// Turkish has dotted and dotless i as separate letters, German 'ß' compares equal to "ss".
+pub fn fold_case(text: &str, language: Langage, locale: CaseLocale) -> String {
+  match (locale, language) {
+    (CaseLocale::Turkish, _) => text.replace('I', "ı").replace('İ', "i").to_lowercase(),
+    (CaseLocale::German, _) => text.to_lowercase().replace('ß', "ss"),
+    (CaseLocale::Default, _) => text.to_lowercase(),
+  }
+}
+
+// Finds the words in text folded by fold_case, accented letters like the ones of "café" are letters of English words too.
+// The ASCII only default_word_regex is still there for --word-regex.
+pub fn language_word_regex(_language: Langage, _locale: CaseLocale) -> Regex {
+  Regex::new(r"(?msx)(?:(?P<word>[\p{Ll}']+?)[^\p{Ll}']+)").unwrap()
+}
+
+// Finds the words of not yet folded text
+pub fn language_letters_regex(_language: Langage, _locale: CaseLocale) -> Regex {
+  Regex::new(r"[\p{L}']+").unwrap()
+}
+
+// The 'word' group of the regex is the word, without one the whole match is
+fn word_matches<'t: 'r, 'r>(lowercase_subs_text: &'t str, word_regex: &'r Regex) -> impl Iterator<Item = &'t str> + 'r {
+  word_regex.captures_iter(lowercase_subs_text)
+    .filter_map(|caps| caps.name("word").or_else(|| caps.get(0)))
+    .map(|word| word.as_str())
+    .filter(|word| !word.is_empty())
+}
+
+pub fn count_sub_words<'a>(lowercase_subs_text: &'a str, word_regex: &Regex) -> HashMap<&'a str, usize> {
+  let mut counts = HashMap::new();
+
+  for text in word_matches(lowercase_subs_text, word_regex) {
+    *counts.entry(text).or_insert(0) += 1;
+  }
+
+  counts
+}
+
+// The first sub text each word appears in, with its lines joined
+pub fn find_word_examples<'a, I>(sub_texts: I, word_regex: &Regex, language: Langage, locale: CaseLocale) -> HashMap<String, String> where I: IntoIterator<Item = &'a str> {
+  let mut examples = HashMap::new();
+
+  for sub_text in sub_texts {
+    let example = sub_text.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    // The regex needs a separator after the last word
+    let lowercase_text = fold_case(sub_text, language, locale) + "\n";
+
+    for word in word_matches(&lowercase_text, word_regex) {
+      examples.entry(String::from(word)).or_insert_with(|| example.clone());
+    }
+  }
+
+  examples
+}
+
+// The words are expected folded by fold_case already
+pub fn has_any_word(text: &str, words: &HashSet<String>, word_regex: &Regex, language: Langage, locale: CaseLocale) -> bool {
+  // The regex needs a separator after the last word
+  let lowercase_text = fold_case(text, language, locale) + "\n";
+  let mut text_words = word_matches(&lowercase_text, word_regex);
+  text_words.any(|word| words.contains(word))
+}
+
+pub fn parse_sub_words<'a>(lowercase_subs_text: &'a str, word_regex: &Regex) -> HashMap<&'a str, Word<'a>> {
+  let mut sub_words: HashMap<&str, Word> = HashMap::new();
+
+  for text in word_matches(lowercase_subs_text, word_regex) {
+
+    sub_words.insert(text, Word {
+      text,
+      kind: WordKind::New,
+      weight: None,
+    });
+  }
+
+  sub_words
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn word_set(words: &HashMap<&str, Word>) -> HashSet<(&'static str, String, Option<String>)> {
+    words.values().map(|word| (word.kind.name(), String::from(word.text), word.weight.map(|weight| weight.to_string()))).collect()
+  }
+
+  #[test]
+  fn db_words_survive_a_round_trip() {
+    let db_text = "k:the\r\nu:ship:3\r\n?:dawn\r\nu:of course:0.5\r\n?:can't:12\r\nk:sail\n";
+    let words = parse_db_words(db_text);
+    let stringified = stringify_db_words(words.values(), &DEFAULT_DB_ORDER);
+    let reparsed = parse_db_words(&stringified);
+
+    assert_eq!(words.len(), 6);
+    assert_eq!(stringified, "?:can't:12\r\n?:dawn\r\nu:of course:0.5\r\nu:ship:3\r\nk:sail\r\nk:the\r\n");
+    assert_eq!(word_set(&words), word_set(&reparsed));
+  }
+
+  #[test]
+  fn acronyms_are_told_from_capitalized_words() {
+    assert!(is_acronym("NASA"));
+    assert!(is_acronym("FBI"));
+    assert!(!is_acronym("Nasa"));
+    assert!(!is_acronym("I"));
+    assert_eq!(strip_acronyms("Call NASA now"), "Call  now");
+    assert_eq!(strip_acronyms("The ship is ready"), "The ship is ready");
+  }
+
+  #[test]
+  fn url_only_cue_is_non_linguistic() {
+    assert!(is_non_linguistic("https://example.com/path?q=1"));
+    assert!(is_non_linguistic("www.example.com!"));
+    assert!(!is_non_linguistic("Go to https://example.com now"));
+    assert!(!is_non_linguistic("Hello there"));
+  }
+
+  #[test]
+  fn accented_english_words_are_whole() {
+    let lowercase_text = fold_case("A Café for the naïve.\r\n", Langage::EN, CaseLocale::Default);
+    let words = parse_sub_words(&lowercase_text, &language_word_regex(Langage::EN, CaseLocale::Default));
+
+    let mut texts: Vec<&str> = words.keys().cloned().collect();
+    texts.sort();
+    assert_eq!(texts, vec!["a", "café", "for", "naïve", "the"]);
+
+    let ascii_words = parse_sub_words(&lowercase_text, &default_word_regex());
+    assert!(!ascii_words.contains_key("café"));
+  }
+
+  #[test]
+  fn case_is_folded_by_locale() {
+    assert_eq!(fold_case("IRMAK İSTANBUL", Langage::EN, CaseLocale::Turkish), "ırmak istanbul");
+    assert_eq!(fold_case("Straße STRASSE", Langage::DE, CaseLocale::German), "strasse strasse");
+    assert_eq!(fold_case("ÉTÉ", Langage::FR, CaseLocale::Default), "été");
+    assert_eq!(fold_case("Istanbul", Langage::EN, CaseLocale::Default), "istanbul");
+  }
+}
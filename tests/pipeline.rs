@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::time::Duration;
+
+use srt_translator::*;
+
+// Lines are changed so they do not look like untranslated passthrough, the requests are kept to check chunking
+struct MockTranslator {
+  requests: RefCell<Vec<String>>,
+}
+
+impl MockTranslator {
+  fn new() -> MockTranslator {
+    MockTranslator {
+      requests: RefCell::new(Vec::new()),
+    }
+  }
+}
+
+impl ETranslate for MockTranslator {
+  fn translate(&self, text: String, _in: Langage, _out: Langage) -> Option<String> {
+    let translation = text.lines().map(|line| format!("~{}", line)).collect::<Vec<_>>().join("\r\n");
+    self.requests.borrow_mut().push(text);
+    Some(translation)
+  }
+}
+
+const SUBS: &str = "\
+1\r\n00:00:01,000 --> 00:00:02,000\r\nThe ship is ready.\r\n\r\n\
+2\r\n00:00:03,000 --> 00:00:04,000\r\nWe sail at dawn.\r\n\r\n\
+3\r\n00:00:05,000 --> 00:00:06,000\r\nThe captain is ready.\r\n\r\n";
+
+const DATABASE: &str = "k:the\r\nk:is\r\nk:ready\r\nk:we\r\nk:at\r\nu:ship\r\nu:sail\r\n?:dawn\r\n";
+
+fn options(max_chunk_size: usize) -> TranslateOptions {
+  TranslateOptions::builder(Langage::EN, Langage::RU)
+    .chunk_delay(Duration::from_secs(0))
+    .max_chunk_size(max_chunk_size)
+    .build()
+}
+
+#[test]
+fn bilingual_output_highlights_unknown_words() {
+  let translator = MockTranslator::new();
+  let run_options = RunOptions::new(options(DEFAULT_MAX_CHUNK_SIZE)).database(DATABASE);
+  let output = run_with_translator(&translator, &run_options, SUBS).unwrap();
+
+  // "captain" is not in the database, so the third sub is new to it and gets translated too
+  assert_eq!(output, "\
+1\n00:00:01,000 --> 00:00:02,000\nThe <font color=\"#FFFF80\">ship</font> is ready.\r\n~The ship is ready.\n\n\
+2\n00:00:03,000 --> 00:00:04,000\nWe <font color=\"#FFFF80\">sail</font> at <font color=\"#FFFF80\">dawn</font>.\r\n~We sail at dawn.\n\n\
+3\n00:00:05,000 --> 00:00:06,000\nThe <font color=\"#FFFF80\">captain</font> is ready.\r\n~The captain is ready.\n\n");
+  assert_eq!(*translator.requests.borrow(), vec!["The ship is ready.\r\nWe sail at dawn.\r\nThe captain is ready.\r\n"]);
+}
+
+#[test]
+fn translation_only_output_keeps_untranslated_subs() {
+  let translator = MockTranslator::new();
+  let run_options = RunOptions::new(options(DEFAULT_MAX_CHUNK_SIZE))
+    .database(&format!("{}k:captain\r\n", DATABASE))
+    .layout(Layout::TranslationOnly);
+  let output = run_with_translator(&translator, &run_options, SUBS).unwrap();
+
+  assert_eq!(output, "\
+1\n00:00:01,000 --> 00:00:02,000\n~The ship is ready.\n\n\
+2\n00:00:03,000 --> 00:00:04,000\n~We sail at dawn.\n\n\
+3\n00:00:05,000 --> 00:00:06,000\nThe captain is ready.\n\n");
+}
+
+#[test]
+fn chunks_end_before_exceeding_max_size() {
+  let translator = MockTranslator::new();
+  let run_options = RunOptions::new(options(40)).database(DATABASE);
+  let output = run_with_translator(&translator, &run_options, SUBS).unwrap();
+
+  assert_eq!(*translator.requests.borrow(), vec![
+    "The ship is ready.\r\nWe sail at dawn.\r\n",
+    "The captain is ready.\r\n",
+  ]);
+
+  // Chunking must not shift translations between subs
+  let subs = parse_subs(&output).unwrap();
+  let translations: Vec<&str> = subs.iter().map(|sub| sub.text.lines().nth(1).unwrap()).collect();
+  assert_eq!(translations, vec!["~The ship is ready.", "~We sail at dawn.", "~The captain is ready."]);
+}
+
+// Library users start from the command line defaults and change only what they need
+#[test]
+fn usage_example() -> std::result::Result<(), Box<dyn std::error::Error>> {
+  let options = TranslateOptions::builder(Langage::EN, Langage::RU)
+    .highlight_color("green")?
+    .chunk_delay(Duration::from_secs(0))
+    .build();
+  let run_options = RunOptions::new(options)
+    .database("k:the\r\nk:is\r\nk:ready\r\n")
+    .layout(Layout::TranslationOnly);
+
+  // run(&run_options, SUBS) would send the subs to Google Translate instead of the mock
+  let output = run_with_translator(&MockTranslator::new(), &run_options, SUBS)?;
+
+  assert!(output.starts_with("1\n00:00:01,000 --> 00:00:02,000\n~The ship is ready.\n\n"));
+  Ok(())
+}
+
+#[test]
+fn cached_lines_are_reused_only_under_the_same_context() {
+  let cache_path = std::env::temp_dir().join(format!("srt-translator-context-{}.cache", std::process::id()));
+  let _ = std::fs::remove_file(&cache_path);
+  let run_options = |context: &str| {
+    let options = TranslateOptions::builder(Langage::EN, Langage::RU)
+      .chunk_delay(Duration::from_secs(0))
+      .context(context)
+      .build();
+    RunOptions::new(options).database(DATABASE).cache_path(&cache_path)
+  };
+
+  let first_translator = MockTranslator::new();
+  let first_output = run_with_translator(&first_translator, &run_options("A pirate movie"), SUBS).unwrap();
+  let second_translator = MockTranslator::new();
+  let second_output = run_with_translator(&second_translator, &run_options("A pirate movie"), SUBS).unwrap();
+  let other_translator = MockTranslator::new();
+  run_with_translator(&other_translator, &run_options("A cooking show"), SUBS).unwrap();
+  std::fs::remove_file(&cache_path).unwrap();
+
+  assert_eq!(*first_translator.requests.borrow(), vec!["A pirate movie\r\nThe ship is ready.\r\nWe sail at dawn.\r\nThe captain is ready.\r\n"]);
+  assert!(second_translator.requests.borrow().is_empty());
+  assert_eq!(second_output, first_output);
+  assert_eq!(*other_translator.requests.borrow(), vec!["A cooking show\r\nThe ship is ready.\r\nWe sail at dawn.\r\nThe captain is ready.\r\n"]);
+}